@@ -1,14 +1,147 @@
+use std::fmt::Display;
+
+use crate::unicode::Property;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Class {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Class {
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            Class::Digit => c.is_ascii_digit(),
+            Class::Word => c.is_ascii_alphanumeric() || c == '_',
+            Class::Space => matches!(c, ' ' | '\t'..='\r'),
+        }
+    }
+
+    /// The sorted inclusive ranges of every character in the class
+    pub fn ranges(self) -> &'static [(u32, u32)] {
+        match self {
+            Class::Digit => &[(0x30, 0x39)],
+            Class::Word => &[(0x30, 0x39), (0x41, 0x5A), (0x5F, 0x5F), (0x61, 0x7A)],
+            Class::Space => &[(0x09, 0x0D), (0x20, 0x20)],
+        }
+    }
+
+    #[cfg(test)]
+    pub fn to_string(self, negated: bool) -> String {
+        let c = match self {
+            Class::Digit => 'd',
+            Class::Word => 'w',
+            Class::Space => 's',
+        };
+
+        if negated {
+            format!("\\{}", c.to_ascii_uppercase())
+        } else {
+            format!("\\{}", c)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Assertion {
+    StartLine,
+    EndLine,
+    StartText,
+    EndText,
+    EndTextOptionalNewline,
+    WordBoundary,
+    NotWordBoundary,
+    UnicodeWordBoundary,
+    NotUnicodeWordBoundary,
+}
+
+impl Assertion {
+    /// Whether the assertion holds at the byte offset index of string, only the chars either side of index are decoded
+    pub fn is_match(&self, string: &str, index: usize) -> bool {
+        let prev = || string[..index].chars().next_back();
+        let next = || string[index..].chars().next();
+        let is_boundary = |is_word: fn(char) -> bool| prev().is_some_and(is_word) != next().is_some_and(is_word);
+
+        match self {
+            Assertion::StartLine => prev().is_none_or(|c| c == '\n'),
+            Assertion::EndLine => next().is_none_or(|c| c == '\n'),
+            Assertion::StartText => index == 0,
+            Assertion::EndText => index >= string.len(),
+            Assertion::EndTextOptionalNewline => index >= string.len() || &string[index..] == "\n",
+            Assertion::WordBoundary => is_boundary(|c| Class::Word.contains(c)),
+            Assertion::NotWordBoundary => !is_boundary(|c| Class::Word.contains(c)),
+            Assertion::UnicodeWordBoundary => is_boundary(|c| c.is_alphanumeric() || c == '_'),
+            Assertion::NotUnicodeWordBoundary => !is_boundary(|c| c.is_alphanumeric() || c == '_'),
+        }
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assertion::StartLine => f.write_str("^"),
+            Assertion::EndLine => f.write_str("$"),
+            Assertion::StartText => f.write_str("\\A"),
+            Assertion::EndText => f.write_str("\\z"),
+            Assertion::EndTextOptionalNewline => f.write_str("\\Z"),
+            Assertion::WordBoundary => f.write_str("\\b"),
+            Assertion::NotWordBoundary => f.write_str("\\B"),
+            Assertion::UnicodeWordBoundary => f.write_str("unicode \\b"),
+            Assertion::NotUnicodeWordBoundary => f.write_str("unicode \\B"),
+        }
+    }
+}
+
+/// An operation between the two sides of a set, "&&", "--" or "~~"
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SetOperation {
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+impl Display for SetOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetOperation::Intersection => f.write_str("&&"),
+            SetOperation::Difference => f.write_str("--"),
+            SetOperation::SymmetricDifference => f.write_str("~~"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum SetSymbol {
     Char(char),
     Range(u32, u32),
+    Class(Class),
+    NotClass(Class),
+    Property(Property),
+    NotProperty(Property),
+    Set(Vec<SetSymbol>),
+    NotSet(Vec<SetSymbol>),
+    Operation(SetOperation, Vec<SetSymbol>, Vec<SetSymbol>),
 }
 
 impl SetSymbol {
+    #[cfg(test)]
     pub fn to_string(&self) -> String {
         match self {
             SetSymbol::Char(c) => format!("'{}'", c),
             SetSymbol::Range(start, end) => format!("c{}-c{}", start, end),
+            SetSymbol::Class(class) => class.to_string(false),
+            SetSymbol::NotClass(class) => class.to_string(true),
+            SetSymbol::Property(property) => format!("\\p{{{}}}", property),
+            SetSymbol::NotProperty(property) => format!("\\P{{{}}}", property),
+            SetSymbol::Set(set) => format!("[{}]", set.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", ")),
+            SetSymbol::NotSet(set) => format!("[^{}]", set.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", ")),
+            SetSymbol::Operation(operation, lhs, rhs) => format!(
+                "[{}] {} [{}]",
+                lhs.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", "),
+                operation,
+                rhs.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", "),
+            ),
         }
     }
 }
@@ -20,21 +153,37 @@ pub enum Token {
     OneOrMore,
     Or,
     OpenParenthesis,
+    OpenNamedGroup(String),
+    OpenNonCapturingGroup(Vec<(char, bool)>),
+    SetFlags(Vec<(char, bool)>),
     CloseParenthesis,
+    Exactly(usize),
     From(usize),
     To(usize),
     Between(usize, usize),
+    LazyZeroOrMore,
+    LazyOptional,
+    LazyOneOrMore,
+    LazyFrom(usize),
+    LazyTo(usize),
+    LazyBetween(usize, usize),
     Symbol(char),
     Set(Vec<SetSymbol>),
     NotSet(Vec<SetSymbol>),
+    Class(Class),
+    NotClass(Class),
+    Property(Property),
+    NotProperty(Property),
+    Assertion(Assertion),
     Any,
+    Error(String),
 }
 
 trait ComplexParse {
     fn parse(&self, lexer: &mut Lexer) -> Option<Token>;
 }
 
-const PARSE_TABLE: [(char, Token); 7] = [
+const PARSE_TABLE: [(char, Token); 9] = [
     ('*', Token::ZeroOrMore),
     ('?', Token::Optional),
     ('+', Token::OneOrMore),
@@ -42,26 +191,217 @@ const PARSE_TABLE: [(char, Token); 7] = [
     ('(', Token::OpenParenthesis),
     (')', Token::CloseParenthesis),
     ('.', Token::Any),
+    ('^', Token::Assertion(Assertion::StartLine)),
+    ('$', Token::Assertion(Assertion::EndLine)),
 ];
 
-const COMPLEX_PARSE_TABLE: [(char, &dyn ComplexParse); 5] = [
+const COMPLEX_PARSE_TABLE: [(char, &dyn ComplexParse); 10] = [
+    ('\\', &ParseEscape{}),
+    ('(', &ParseNamedGroup{}),
+    ('(', &ParseFlagGroup{}),
+    ('{', &ParseExactly{}),
     ('{', &ParseFrom{}),
     ('{', &ParseTo{}),
     ('{', &ParseBetween{}),
+    ('{', &ParseInvalidRepetition{}),
     ('[', &ParseNotSet{}),
     ('[', &ParseSet{}),
 ];
 
+const ESCAPE_TABLE: [(char, char); 4] = [
+    ('n', '\n'),
+    ('t', '\t'),
+    ('r', '\r'),
+    ('0', '\0'),
+];
+
+const CLASS_TABLE: [(char, Class, bool); 6] = [
+    ('d', Class::Digit, false),
+    ('D', Class::Digit, true),
+    ('w', Class::Word, false),
+    ('W', Class::Word, true),
+    ('s', Class::Space, false),
+    ('S', Class::Space, true),
+];
+
+const ASSERTION_TABLE: [(char, Assertion); 5] = [
+    ('A', Assertion::StartText),
+    ('z', Assertion::EndText),
+    ('Z', Assertion::EndTextOptionalNewline),
+    ('b', Assertion::WordBoundary),
+    ('B', Assertion::NotWordBoundary),
+];
+
+const POSIX_CLASS_TABLE: [(&str, &[(char, char)]); 14] = [
+    ("alnum", &[('0', '9'), ('A', 'Z'), ('a', 'z')]),
+    ("alpha", &[('A', 'Z'), ('a', 'z')]),
+    ("ascii", &[('\x00', '\x7F')]),
+    ("blank", &[('\t', '\t'), (' ', ' ')]),
+    ("cntrl", &[('\x00', '\x1F'), ('\x7F', '\x7F')]),
+    ("digit", &[('0', '9')]),
+    ("graph", &[('!', '~')]),
+    ("lower", &[('a', 'z')]),
+    ("print", &[(' ', '~')]),
+    ("punct", &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')]),
+    ("space", &[('\t', '\r'), (' ', ' ')]),
+    ("upper", &[('A', 'Z')]),
+    ("word", &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+    ("xdigit", &[('0', '9'), ('A', 'F'), ('a', 'f')]),
+];
+
+const METACHARACTERS: [char; 17] = ['\\', '*', '?', '+', '|', '(', ')', '.', '[', ']', '{', '}', '^', '$', '-', '#', ' '];
+
+const FLAGS: [char; 5] = ['i', 'm', 's', 'x', 'u'];
+
+struct ParseEscape {}
+impl ComplexParse for ParseEscape {
+    fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
+        if let Some((class, negated)) = lexer.parse_class() {
+            if negated {
+                return Some(Token::NotClass(class))
+            } else {
+                return Some(Token::Class(class))
+            }
+        }
+
+        match lexer.parse_property() {
+            Some(Ok((property, true))) => return Some(Token::NotProperty(property)),
+            Some(Ok((property, false))) => return Some(Token::Property(property)),
+            Some(Err(msg)) => return Some(Token::Error(msg)),
+            None => (),
+        }
+
+        if let Some(assertion) = lexer.parse_assertion() {
+            return Some(Token::Assertion(assertion))
+        }
+
+        match lexer.parse_escape() {
+            Ok(c) => Some(Token::Symbol(c)),
+            Err(msg) => Some(Token::Error(msg)),
+        }
+    }
+}
+
+struct ParseNamedGroup {}
+impl ComplexParse for ParseNamedGroup {
+    fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
+        let fallback = lexer.pos();
+
+        if lexer.data.get(lexer.index) != Some(&'?') {
+            return None
+        }
+        lexer.index += 1;
+
+        if lexer.data.get(lexer.index) == Some(&'P') {
+            lexer.index += 1;
+        }
+
+        if lexer.data.get(lexer.index) != Some(&'<') {
+            lexer.seek(fallback);
+            return None
+        }
+        lexer.index += 1;
+
+        let start = lexer.index;
+        while let Some(c) = lexer.data.get(lexer.index) {
+            if *c == '>' {
+                break;
+            }
+            lexer.index += 1;
+        }
+
+        if lexer.index >= lexer.data.len() {
+            return Some(Token::Error("expected '>' to close capture group name".to_string()))
+        }
+
+        let name = lexer.data[start..lexer.index].iter().collect::<String>();
+        lexer.index += 1;
+
+        let mut chars = name.chars();
+        let is_valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        if is_valid {
+            Some(Token::OpenNamedGroup(name))
+        } else {
+            Some(Token::Error(format!("invalid capture group name '{}'", name)))
+        }
+    }
+}
+
+struct ParseFlagGroup {}
+impl ComplexParse for ParseFlagGroup {
+    fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
+        if lexer.data.get(lexer.index) != Some(&'?') {
+            return None
+        }
+        lexer.index += 1;
+
+        let mut flags = vec![];
+        let mut enabled = true;
+
+        while let Some(c) = lexer.data.get(lexer.index) {
+            lexer.index += 1;
+
+            match c {
+                ':' => return Some(Token::OpenNonCapturingGroup(flags)),
+                ')' => if flags.is_empty() {
+                    return Some(Token::Error("expected at least one flag in flag group".to_string()))
+                } else {
+                    return Some(Token::SetFlags(flags))
+                },
+                '-' => if enabled {
+                    enabled = false;
+                } else {
+                    return Some(Token::Error("a flag group can only contain one '-'".to_string()))
+                },
+                c if FLAGS.contains(c) => if flags.iter().any(|(flag, _)| flag == c) {
+                    return Some(Token::Error(format!("the flag '{}' is repeated", c)))
+                } else {
+                    flags.push((*c, enabled));
+                },
+                c => return Some(Token::Error(format!("unknown flag '{}'", c))),
+            }
+        }
+
+        Some(Token::Error("expected ':' or ')' to close flag group".to_string()))
+    }
+}
+
+struct ParseExactly {}
+impl ComplexParse for ParseExactly {
+    fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
+        let fallback = lexer.pos();
+        let Some(num) = lexer.parse_number() else {
+            lexer.seek(fallback);
+            return None
+        };
+
+        lexer.consume_whitespace();
+
+        if lexer.data.get(lexer.index) != Some(&'}') {
+            lexer.seek(fallback);
+            return None
+        } else {
+            lexer.index += 1
+        }
+
+        Some(Token::Exactly(num))
+    }
+}
+
 struct ParseFrom {}
 impl ComplexParse for ParseFrom {
     fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
         let fallback = lexer.pos();
-        let num = lexer.parse_number()?;
+        let Some(num) = lexer.parse_number() else {
+            lexer.seek(fallback);
+            return None
+        };
 
         lexer.consume_whitespace();
 
-        let next = lexer.data.get(lexer.index)?; 
-        if *next != ',' {
+        if lexer.data.get(lexer.index) != Some(&',') {
             lexer.seek(fallback);
             return None
         } else {
@@ -70,8 +410,7 @@ impl ComplexParse for ParseFrom {
 
         lexer.consume_whitespace();
 
-        let next = lexer.data.get(lexer.index)?; 
-        if *next != '}' {
+        if lexer.data.get(lexer.index) != Some(&'}') {
             lexer.seek(fallback);
             return None
         } else {
@@ -88,8 +427,7 @@ impl ComplexParse for ParseTo {
         let fallback = lexer.pos();
         lexer.consume_whitespace();
 
-        let next = lexer.data.get(lexer.index)?; 
-        if *next != ',' {
+        if lexer.data.get(lexer.index) != Some(&',') {
             lexer.seek(fallback);
             return None
         } else {
@@ -98,12 +436,14 @@ impl ComplexParse for ParseTo {
 
         lexer.consume_whitespace();
 
-        let num = lexer.parse_number()?;
+        let Some(num) = lexer.parse_number() else {
+            lexer.seek(fallback);
+            return None
+        };
 
         lexer.consume_whitespace();
 
-        let next = lexer.data.get(lexer.index)?; 
-        if *next != '}' {
+        if lexer.data.get(lexer.index) != Some(&'}') {
             lexer.seek(fallback);
             return None
         } else {
@@ -120,12 +460,14 @@ impl ComplexParse for ParseBetween {
         let fallback = lexer.pos();
         lexer.consume_whitespace();
 
-        let num_a = lexer.parse_number()?;
+        let Some(num_a) = lexer.parse_number() else {
+            lexer.seek(fallback);
+            return None
+        };
 
         lexer.consume_whitespace();
 
-        let next = lexer.data.get(lexer.index)?; 
-        if *next != ',' {
+        if lexer.data.get(lexer.index) != Some(&',') {
             lexer.seek(fallback);
             return None
         } else {
@@ -134,12 +476,14 @@ impl ComplexParse for ParseBetween {
 
         lexer.consume_whitespace();
 
-        let num_b = lexer.parse_number()?;
+        let Some(num_b) = lexer.parse_number() else {
+            lexer.seek(fallback);
+            return None
+        };
 
         lexer.consume_whitespace();
 
-        let next = lexer.data.get(lexer.index)?; 
-        if *next != '}' {
+        if lexer.data.get(lexer.index) != Some(&'}') {
             lexer.seek(fallback);
             return None
         } else {
@@ -150,49 +494,43 @@ impl ComplexParse for ParseBetween {
     }
 }
 
+/// Only used in strict mode, describes why a "{" could not be parsed as a repetition
+struct ParseInvalidRepetition {}
+impl ComplexParse for ParseInvalidRepetition {
+    fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
+        if !lexer.strict {
+            return None
+        }
+
+        let rest = &lexer.data[lexer.index..];
+        let Some(end) = rest.iter().position(|c| *c == '}') else {
+            return Some(Token::Error(format!("missing '}}' to close repetition '{{{}'", rest.iter().collect::<String>())))
+        };
+        let contents = rest[..end].iter().collect::<String>();
+
+        let is_number = |num: &str| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit());
+        let parts = contents.split(',').collect::<Vec<&str>>();
+        if parts.iter().any(|part| is_number(part)) && parts.iter().all(|part| part.is_empty() || is_number(part)) && parts.len() <= 2 {
+            return Some(Token::Error(format!("repetition count in '{{{}}}' is too large", contents)))
+        }
+
+        Some(Token::Error(format!("invalid repetition '{{{}}}', expected {{n}}, {{n,}}, {{,m}} or {{n,m}}", contents)))
+    }
+}
+
 struct ParseSet {}
 impl ComplexParse for ParseSet {
     fn parse(&self, lexer: &mut Lexer) -> Option<Token> {
         let fallback = lexer.pos();
-        let mut data = vec![];
-
-        lexer.consume_whitespace();
 
-        while let Some(c) = lexer.data.get(lexer.index) {
-            if *c == ']' {
-                if data.len() == 0 {
-                    lexer.seek(fallback);
-                    return None
-                } else {
-                    lexer.index += 1;
-                    return Some(Token::Set(data))
-                }
-            } else if *c == '-' {
-                if data.len() == 0 || lexer.index == data.len() - 1 {
-                    lexer.seek(fallback);
-                    return None
-                } else {
-                    match data.last_mut().unwrap() {
-                        SetSymbol::Char(c) => {
-                            lexer.index += 1;
-                            let symbol = SetSymbol::Range(*c as u32, *lexer.data.get(lexer.index).unwrap() as u32);
-                            *data.last_mut().unwrap() = symbol;
-                        },
-                        SetSymbol::Range(_, _) => {
-                            lexer.seek(fallback);
-                            return None
-                        },
-                    }
-                }
-            } else {
-                data.push(SetSymbol::Char(*c))
-            }
-            lexer.index += 1;
-            lexer.consume_whitespace();
+        match lexer.parse_set_items() {
+            Some(Ok(set)) => Some(Token::Set(set)),
+            Some(Err(msg)) => Some(Token::Error(msg)),
+            None => {
+                lexer.seek(fallback);
+                None
+            },
         }
-
-        lexer.seek(fallback);
-        None
     }
 }
 
@@ -213,6 +551,7 @@ impl ComplexParse for ParseNotSet {
         let set = ParseSet{}.parse(lexer);
         match set {
             Some(Token::Set(set)) => return Some(Token::NotSet(set)),
+            Some(Token::Error(msg)) => return Some(Token::Error(msg)),
             _ => {
                 lexer.seek(fallback);
                 return None
@@ -224,6 +563,8 @@ impl ComplexParse for ParseNotSet {
 pub struct Lexer{
     data: Vec<char>,
     index: usize,
+    extended: bool,
+    strict: bool,
 }
 
 impl<'a> Iterator for Lexer {
@@ -236,7 +577,17 @@ impl<'a> Iterator for Lexer {
 
 impl Lexer {
     pub fn new(data: &str) -> Lexer {
-        Lexer { data: data.chars().collect(), index: 0 }
+        Lexer { data: data.chars().collect(), index: 0, extended: false, strict: false }
+    }
+
+    /// In strict mode a "{" that does not start a valid repetition is an error instead of a literal "{"
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// In extended mode all whitespace and comments starting with "#" are ignored
+    pub fn set_extended(&mut self, extended: bool) {
+        self.extended = extended;
     }
 
     pub fn pos(&self) -> usize {
@@ -267,14 +618,14 @@ impl Lexer {
             for (token_char, parser) in COMPLEX_PARSE_TABLE {
                 if c == token_char {
                     if let Some(token) = parser.parse(self) {
-                        return Some(token)
+                        return Some(self.parse_lazy(token))
                     }
                 }
             }
 
             for (token_char, token) in PARSE_TABLE {
                 if c == token_char {
-                    return Some(token.clone())
+                    return Some(self.parse_lazy(token.clone()))
                 }
             }
             Some(Token::Symbol(c))
@@ -285,8 +636,39 @@ impl Lexer {
         }
     }
 
+    fn parse_lazy(&mut self, token: Token) -> Token {
+        if self.data.get(self.index) != Some(&'?') {
+            return token
+        }
+
+        let lazy = match token {
+            Token::ZeroOrMore => Token::LazyZeroOrMore,
+            Token::Optional => Token::LazyOptional,
+            Token::OneOrMore => Token::LazyOneOrMore,
+            Token::From(from) => Token::LazyFrom(from),
+            Token::To(to) => Token::LazyTo(to),
+            Token::Between(from, to) => Token::LazyBetween(from, to),
+            // an exact repetition always matches the same number of times, so being lazy changes nothing
+            Token::Exactly(count) => Token::Exactly(count),
+            token => return token,
+        };
+        self.index += 1;
+
+        lazy
+    }
+
     fn consume_whitespace(&mut self) {
         while let Some(c) = self.data.get(self.index) {
+            if self.extended && *c == '#' {
+                while self.data.get(self.index).is_some_and(|c| *c != '\n') {
+                    self.index += 1;
+                }
+                continue;
+            } else if self.extended && c.is_whitespace() {
+                self.index += 1;
+                continue;
+            }
+
             if *c != '\t' && *c != '\r' &&
                 *c != '\n' {
                     break;
@@ -296,6 +678,290 @@ impl Lexer {
         }
     }
 
+    fn parse_class(&mut self) -> Option<(Class, bool)> {
+        let c = self.data.get(self.index)?;
+
+        for (class_char, class, negated) in CLASS_TABLE {
+            if *c == class_char {
+                self.index += 1;
+                return Some((class, negated))
+            }
+        }
+
+        None
+    }
+
+    /// Parses the contents of a set up to and including its closing "]", or None if the set is never closed
+    fn parse_set_items(&mut self) -> Option<Result<Vec<SetSymbol>, String>> {
+        let mut data = vec![];
+        let mut operation: Option<(SetOperation, Vec<SetSymbol>)> = None;
+
+        self.consume_whitespace();
+
+        while let Some(c) = self.data.get(self.index) {
+            let next_operation = match (*c, self.data.get(self.index + 1)) {
+                ('&', Some('&')) => Some(SetOperation::Intersection),
+                ('-', Some('-')) if !data.is_empty() => Some(SetOperation::Difference),
+                ('~', Some('~')) => Some(SetOperation::SymmetricDifference),
+                _ => None,
+            };
+
+            if let Some(next_operation) = next_operation {
+                if data.is_empty() {
+                    return Some(Err(format!("missing left operand of '{}' in set", next_operation)))
+                }
+                self.index += 2;
+
+                let lhs = match operation.take() {
+                    Some((operation, lhs)) => vec![SetSymbol::Operation(operation, lhs, data)],
+                    None => data,
+                };
+                operation = Some((next_operation, lhs));
+                data = vec![];
+            } else if *c == ']' {
+                match operation {
+                    Some((operation, _)) if data.is_empty() => {
+                        return Some(Err(format!("missing right operand of '{}' in set", operation)))
+                    },
+                    None if data.is_empty() => return None,
+                    Some((operation, lhs)) => {
+                        self.index += 1;
+                        return Some(Ok(vec![SetSymbol::Operation(operation, lhs, data)]))
+                    },
+                    None => {
+                        self.index += 1;
+                        return Some(Ok(data))
+                    },
+                }
+            } else if *c == '-' {
+                self.index += 1;
+
+                let start = match data.last() {
+                    Some(SetSymbol::Char(start)) if self.data.get(self.index) != Some(&']') => *start,
+                    _ => {
+                        data.push(SetSymbol::Char('-'));
+                        self.consume_whitespace();
+                        continue;
+                    },
+                };
+
+                let end = match self.parse_set_char()? {
+                    Ok(end) => end,
+                    Err(msg) => return Some(Err(msg)),
+                };
+                *data.last_mut().unwrap() = SetSymbol::Range(start as u32, end as u32);
+            } else if *c == '[' {
+                let posix_class = if self.data.get(self.index + 1) == Some(&':') {
+                    self.parse_posix_class()
+                } else {
+                    None
+                };
+
+                match posix_class {
+                    Some(Ok(ranges)) => data.extend(ranges),
+                    Some(Err(msg)) => return Some(Err(msg)),
+                    None => {
+                        self.index += 1;
+                        self.consume_whitespace();
+                        let negated = self.data.get(self.index) == Some(&'^');
+                        if negated {
+                            self.index += 1;
+                        }
+
+                        match self.parse_set_items()? {
+                            Ok(set) if negated => data.push(SetSymbol::NotSet(set)),
+                            Ok(set) => data.push(SetSymbol::Set(set)),
+                            Err(msg) => return Some(Err(msg)),
+                        }
+                    },
+                }
+            } else if *c == '\\' {
+                self.index += 1;
+                match self.parse_class() {
+                    Some((class, true)) => data.push(SetSymbol::NotClass(class)),
+                    Some((class, false)) => data.push(SetSymbol::Class(class)),
+                    None => match self.parse_property() {
+                        Some(Ok((property, true))) => data.push(SetSymbol::NotProperty(property)),
+                        Some(Ok((property, false))) => data.push(SetSymbol::Property(property)),
+                        Some(Err(msg)) => return Some(Err(msg)),
+                        None => match self.parse_escape() {
+                            Ok(c) => data.push(SetSymbol::Char(c)),
+                            Err(msg) => return Some(Err(msg)),
+                        },
+                    },
+                }
+            } else {
+                data.push(SetSymbol::Char(*c));
+                self.index += 1;
+            }
+            self.consume_whitespace();
+        }
+
+        None
+    }
+
+    /// Parses "[:name:]" or "[:^name:]" into the ranges of the class, or None if it is not a POSIX class
+    fn parse_posix_class(&mut self) -> Option<Result<Vec<SetSymbol>, String>> {
+        let start = self.index + 2;
+        let end = start + self.data[start..].iter().position(|c| *c == ':')?;
+        if self.data.get(end + 1) != Some(&']') {
+            return None
+        }
+
+        let (negated, name) = match self.data[start..end].split_first() {
+            Some(('^', name)) => (true, name.iter().collect::<String>()),
+            _ => (false, self.data[start..end].iter().collect::<String>()),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None
+        }
+        self.index = end + 2;
+
+        let Some((_, ranges)) = POSIX_CLASS_TABLE.iter().find(|(class_name, _)| *class_name == name) else {
+            return Some(Err(format!("unknown POSIX class '{}'", name)))
+        };
+
+        let mut symbols = vec![];
+        if negated {
+            let mut next = 0u32;
+            for (start, end) in ranges.iter() {
+                if next < *start as u32 {
+                    symbols.push(SetSymbol::Range(next, *start as u32 - 1));
+                }
+                next = *end as u32 + 1;
+            }
+            symbols.push(SetSymbol::Range(next, char::MAX as u32));
+        } else {
+            symbols.extend(ranges.iter().map(|(start, end)| SetSymbol::Range(*start as u32, *end as u32)));
+        }
+
+        Some(Ok(symbols))
+    }
+
+    fn parse_property(&mut self) -> Option<Result<(Property, bool), String>> {
+        let negated = match self.data.get(self.index)? {
+            'p' => false,
+            'P' => true,
+            _ => return None,
+        };
+        self.index += 1;
+
+        let name = match self.data.get(self.index) {
+            Some('{') => {
+                let start = self.index + 1;
+                let Some(end) = self.data[start..].iter().position(|c| *c == '}').map(|end| start + end) else {
+                    return Some(Err("missing closing brace in unicode property".to_string()))
+                };
+                self.index = end + 1;
+                self.data[start..end].iter().collect::<String>()
+            },
+            Some(c) => {
+                self.index += 1;
+                c.to_string()
+            },
+            None => return Some(Err("missing unicode property name".to_string())),
+        };
+
+        match Property::find(&name) {
+            Some(property) => Some(Ok((property, negated))),
+            None => Some(Err(format!("unknown unicode property '{}'", name))),
+        }
+    }
+
+    fn parse_assertion(&mut self) -> Option<Assertion> {
+        let c = self.data.get(self.index)?;
+
+        for (assertion_char, assertion) in ASSERTION_TABLE {
+            if *c == assertion_char {
+                self.index += 1;
+                return Some(assertion)
+            }
+        }
+
+        None
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let c = match self.data.get(self.index) {
+            Some(c) => *c,
+            None => return Err("trailing backslash at the end of the regex".to_string()),
+        };
+        self.index += 1;
+
+        for (escape_char, escaped) in ESCAPE_TABLE {
+            if c == escape_char {
+                return Ok(escaped)
+            }
+        }
+
+        if METACHARACTERS.contains(&c) {
+            return Ok(c)
+        }
+
+        match c {
+            'x' => {
+                if self.data.get(self.index) == Some(&'{') {
+                    return self.parse_braced_hex('x')
+                }
+                let start = self.index;
+                let end = start + 2;
+                if end > self.data.len() {
+                    return Err("expected two hex digits after \\x".to_string())
+                }
+                self.index = end;
+                self.parse_hex(start, end)
+            },
+            'u' => {
+                if self.data.get(self.index) != Some(&'{') {
+                    return Err("expected '{' after \\u".to_string())
+                }
+                self.parse_braced_hex('u')
+            },
+            _ => Err(format!("unknown escape sequence \\{}", c)),
+        }
+    }
+
+    /// Parses the "{..}" of an escape like "\\u{3b1}" or "\\x{41}", starting at the "{"
+    fn parse_braced_hex(&mut self, escape: char) -> Result<char, String> {
+        let start = self.index + 1;
+        let mut end = start;
+        while let Some(c) = self.data.get(end) {
+            if *c == '}' {
+                break;
+            }
+            end += 1;
+        }
+        if end >= self.data.len() {
+            return Err(format!("expected '}}' to close \\{}{{", escape))
+        } else if end == start || end - start > 6 {
+            return Err(format!("expected one to six hex digits in \\{}{{}}", escape))
+        }
+        self.index = end + 1;
+        self.parse_hex(start, end)
+    }
+
+    fn parse_hex(&self, start: usize, end: usize) -> Result<char, String> {
+        let digits = self.data[start..end].iter().collect::<String>();
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid hex digits '{}' in escape sequence", digits))
+        }
+
+        u32::from_str_radix(&digits, 16).ok()
+            .and_then(char::from_u32)
+            .ok_or(format!("'{}' is not a valid unicode scalar value", digits))
+    }
+
+    fn parse_set_char(&mut self) -> Option<Result<char, String>> {
+        let c = *self.data.get(self.index)?;
+        self.index += 1;
+
+        if c == '\\' {
+            Some(self.parse_escape())
+        } else {
+            Some(Ok(c))
+        }
+    }
+
     fn parse_number(&mut self) -> Option<usize> {
         let fallback = self.pos();
         self.consume_whitespace();
@@ -325,7 +991,7 @@ impl Lexer {
 
 #[cfg(test)]
 mod tests {
-    use super::{Lexer, Token, SetSymbol};
+    use super::{Lexer, Token, SetSymbol, SetOperation, Class, Assertion, Property};
 
     #[test] 
     fn test() {
@@ -352,5 +1018,294 @@ mod tests {
             assert_eq!(lexer.next().unwrap(), expected);
         }
     }
+
+    #[test]
+    fn test_sets() {
+        let mut lexer = Lexer::new("[-a][a-][^-a][a-c-e][\\d-z][à-ÿ][α-ω][\\x00-\\x1f][!-/][z-a]");
+        let expected_tokens = [
+            Token::Set(vec![SetSymbol::Char('-'), SetSymbol::Char('a')]),
+            Token::Set(vec![SetSymbol::Char('a'), SetSymbol::Char('-')]),
+            Token::NotSet(vec![SetSymbol::Char('-'), SetSymbol::Char('a')]),
+            Token::Set(vec![SetSymbol::Range('a' as u32, 'c' as u32), SetSymbol::Char('-'), SetSymbol::Char('e')]),
+            Token::Set(vec![SetSymbol::Class(Class::Digit), SetSymbol::Char('-'), SetSymbol::Char('z')]),
+            Token::Set(vec![SetSymbol::Range('à' as u32, 'ÿ' as u32)]),
+            Token::Set(vec![SetSymbol::Range('α' as u32, 'ω' as u32)]),
+            Token::Set(vec![SetSymbol::Range(0x00, 0x1f)]),
+            Token::Set(vec![SetSymbol::Range('!' as u32, '/' as u32)]),
+            Token::Set(vec![SetSymbol::Range('z' as u32, 'a' as u32)]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.peek().unwrap(), expected);
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let mut lexer = Lexer::new("[a-z&&[^aeiou]][\\w--\\d][ab~~bc&&c][[ab]c[^d]][a&b-]");
+        let expected_tokens = [
+            Token::Set(vec![SetSymbol::Operation(
+                SetOperation::Intersection,
+                vec![SetSymbol::Range('a' as u32, 'z' as u32)],
+                vec![SetSymbol::NotSet(vec![SetSymbol::Char('a'), SetSymbol::Char('e'), SetSymbol::Char('i'), SetSymbol::Char('o'), SetSymbol::Char('u')])],
+            )]),
+            Token::Set(vec![SetSymbol::Operation(SetOperation::Difference, vec![SetSymbol::Class(Class::Word)], vec![SetSymbol::Class(Class::Digit)])]),
+            Token::Set(vec![SetSymbol::Operation(
+                SetOperation::Intersection,
+                vec![SetSymbol::Operation(
+                    SetOperation::SymmetricDifference,
+                    vec![SetSymbol::Char('a'), SetSymbol::Char('b')],
+                    vec![SetSymbol::Char('b'), SetSymbol::Char('c')],
+                )],
+                vec![SetSymbol::Char('c')],
+            )]),
+            Token::Set(vec![
+                SetSymbol::Set(vec![SetSymbol::Char('a'), SetSymbol::Char('b')]),
+                SetSymbol::Char('c'),
+                SetSymbol::NotSet(vec![SetSymbol::Char('d')]),
+            ]),
+            Token::Set(vec![SetSymbol::Char('a'), SetSymbol::Char('&'), SetSymbol::Char('b'), SetSymbol::Char('-')]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.peek().unwrap(), expected);
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+
+        assert_eq!(Lexer::new("[&&a]").next(), Some(Token::Error("missing left operand of '&&' in set".to_string())));
+        assert_eq!(Lexer::new("[a--]").next(), Some(Token::Error("missing right operand of '--' in set".to_string())));
+        assert_eq!(Lexer::new("[a[b]").next(), Some(Token::Symbol('[')));
+    }
+
+    #[test]
+    fn test_posix_classes() {
+        let mut lexer = Lexer::new("[[:alpha:]][[:digit:]_-][^[:space:]][[:^xdigit:]][[:a][z:]]");
+        let expected_tokens = [
+            Token::Set(vec![SetSymbol::Range('A' as u32, 'Z' as u32), SetSymbol::Range('a' as u32, 'z' as u32)]),
+            Token::Set(vec![SetSymbol::Range('0' as u32, '9' as u32), SetSymbol::Char('_'), SetSymbol::Char('-')]),
+            Token::NotSet(vec![SetSymbol::Range('\t' as u32, '\r' as u32), SetSymbol::Range(' ' as u32, ' ' as u32)]),
+            Token::Set(vec![
+                SetSymbol::Range(0, '0' as u32 - 1),
+                SetSymbol::Range('9' as u32 + 1, 'A' as u32 - 1),
+                SetSymbol::Range('F' as u32 + 1, 'a' as u32 - 1),
+                SetSymbol::Range('f' as u32 + 1, char::MAX as u32),
+            ]),
+            Token::Set(vec![
+                SetSymbol::Set(vec![SetSymbol::Char(':'), SetSymbol::Char('a')]),
+                SetSymbol::Set(vec![SetSymbol::Char('z'), SetSymbol::Char(':')]),
+            ]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.peek().unwrap(), expected);
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+
+        assert_eq!(Lexer::new("[[:foo:]]").next(), Some(Token::Error("unknown POSIX class 'foo'".to_string())));
+    }
+
+    #[test]
+    fn test_properties() {
+        let letter = Property::find("L").unwrap();
+        let greek = Property::find("Greek").unwrap();
+        let mut lexer = Lexer::new("\\pL\\p{Greek}\\P{sc=Greek}[\\p{L}\\PL-]");
+        let expected_tokens = [
+            Token::Property(letter),
+            Token::Property(greek),
+            Token::NotProperty(greek),
+            Token::Set(vec![SetSymbol::Property(letter), SetSymbol::NotProperty(letter), SetSymbol::Char('-')]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.peek().unwrap(), expected);
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+
+        for invalid in ["\\p", "\\p{L", "\\p{Klingon}", "\\p{}", "\\p{gc=Greek}", "[\\P{Foo}]"] {
+            assert!(matches!(Lexer::new(invalid).next(), Some(Token::Error(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_repetitions() {
+        let mut lexer = Lexer::new("a{3}b{3}?c{2,4}d{,a}");
+        let expected_tokens = [
+            Token::Symbol('a'),
+            Token::Exactly(3),
+            Token::Symbol('b'),
+            Token::Exactly(3),
+            Token::Symbol('c'),
+            Token::Between(2, 4),
+            Token::Symbol('d'),
+            Token::Symbol('{'),
+            Token::Symbol(','),
+            Token::Symbol('a'),
+            Token::Symbol('}'),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.peek().unwrap(), expected);
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+
+        let strict = |string: &str| {
+            let mut lexer = Lexer::new(string);
+            lexer.set_strict(true);
+            lexer.collect::<Vec<Token>>()
+        };
+        assert_eq!(strict("a{3}"), vec![Token::Symbol('a'), Token::Exactly(3)]);
+        assert_eq!(strict("a\\{3"), vec![Token::Symbol('a'), Token::Symbol('{'), Token::Symbol('3')]);
+        assert!(strict("{3").contains(&Token::Error("missing '}' to close repetition '{3'".to_string())));
+        assert!(strict("{a,b}").contains(&Token::Error("invalid repetition '{a,b}', expected {n}, {n,}, {,m} or {n,m}".to_string())));
+        assert!(strict("{}").contains(&Token::Error("invalid repetition '{}', expected {n}, {n,}, {,m} or {n,m}".to_string())));
+        assert!(strict("{ 2 }").contains(&Token::Error("invalid repetition '{ 2 }', expected {n}, {n,}, {,m} or {n,m}".to_string())));
+        assert!(strict("{1,2,3}").contains(&Token::Error("invalid repetition '{1,2,3}', expected {n}, {n,}, {,m} or {n,m}".to_string())));
+        assert!(strict("{99999999999999999999999}").contains(&Token::Error("repetition count in '{99999999999999999999999}' is too large".to_string())));
+    }
+
+    #[test]
+    fn test_lazy() {
+        let mut lexer = Lexer::new("a*?b+?c??d{2,}?e{,2}?f{2,3}?g???\\*?");
+        let expected_tokens = [
+            Token::Symbol('a'),
+            Token::LazyZeroOrMore,
+            Token::Symbol('b'),
+            Token::LazyOneOrMore,
+            Token::Symbol('c'),
+            Token::LazyOptional,
+            Token::Symbol('d'),
+            Token::LazyFrom(2),
+            Token::Symbol('e'),
+            Token::LazyTo(2),
+            Token::Symbol('f'),
+            Token::LazyBetween(2, 3),
+            Token::Symbol('g'),
+            Token::LazyOptional,
+            Token::Optional,
+            Token::Symbol('*'),
+            Token::Optional,
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.peek().unwrap(), expected);
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_escapes() {
+        let mut lexer = Lexer::new("\\*\\(\\.\\|\\[\\\\\\n\\t\\r\\0\\x41\\u{3b1}\\x{42}\\x{1F600}[\\]\\-a\\x30-\\x39]");
+        let expected_tokens = [
+            Token::Symbol('*'),
+            Token::Symbol('('),
+            Token::Symbol('.'),
+            Token::Symbol('|'),
+            Token::Symbol('['),
+            Token::Symbol('\\'),
+            Token::Symbol('\n'),
+            Token::Symbol('\t'),
+            Token::Symbol('\r'),
+            Token::Symbol('\0'),
+            Token::Symbol('A'),
+            Token::Symbol('α'),
+            Token::Symbol('B'),
+            Token::Symbol('😀'),
+            Token::Set(vec![SetSymbol::Char(']'), SetSymbol::Char('-'), SetSymbol::Char('a'), SetSymbol::Range('0' as u32, '9' as u32)]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+        assert_eq!(lexer.next(), None);
+
+        for invalid in ["\\q", "\\", "\\x4", "\\xzz", "\\u{}", "\\u{110000}", "\\u41", "\\x{}", "\\x{41", "\\x{zz}", "\\x{1234567}", "[a\\q]", "[^\\q]"] {
+            assert!(matches!(Lexer::new(invalid).next(), Some(Token::Error(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_classes() {
+        let mut lexer = Lexer::new("\\d\\D\\w\\W\\s\\S[\\d_-][^\\W\\s]");
+        let expected_tokens = [
+            Token::Class(Class::Digit),
+            Token::NotClass(Class::Digit),
+            Token::Class(Class::Word),
+            Token::NotClass(Class::Word),
+            Token::Class(Class::Space),
+            Token::NotClass(Class::Space),
+            Token::Set(vec![SetSymbol::Class(Class::Digit), SetSymbol::Char('_'), SetSymbol::Char('-')]),
+            Token::NotSet(vec![SetSymbol::NotClass(Class::Word), SetSymbol::Class(Class::Space)]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn test_assertions() {
+        let mut lexer = Lexer::new("^$\\A\\z\\Z\\b\\B[\\^$]");
+        let expected_tokens = [
+            Token::Assertion(Assertion::StartLine),
+            Token::Assertion(Assertion::EndLine),
+            Token::Assertion(Assertion::StartText),
+            Token::Assertion(Assertion::EndText),
+            Token::Assertion(Assertion::EndTextOptionalNewline),
+            Token::Assertion(Assertion::WordBoundary),
+            Token::Assertion(Assertion::NotWordBoundary),
+            Token::Set(vec![SetSymbol::Char('^'), SetSymbol::Char('$')]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+        assert_eq!(lexer.next(), None);
+
+        for invalid in ["[\\A]", "[\\b]"] {
+            assert!(matches!(Lexer::new(invalid).next(), Some(Token::Error(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_named_groups() {
+        let mut lexer = Lexer::new("(?<year>(?P<month_1>)");
+        assert_eq!(lexer.next().unwrap(), Token::OpenNamedGroup("year".to_string()));
+        assert_eq!(lexer.next().unwrap(), Token::OpenNamedGroup("month_1".to_string()));
+        assert_eq!(lexer.next().unwrap(), Token::CloseParenthesis);
+        assert_eq!(lexer.next(), None);
+
+        for invalid in ["(?<>", "(?<1a>", "(?P<a-b>", "(?<name"] {
+            assert!(matches!(Lexer::new(invalid).next(), Some(Token::Error(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut lexer = Lexer::new("(?:(?i)(?s-mx:(?-u)");
+        let expected_tokens = [
+            Token::OpenNonCapturingGroup(vec![]),
+            Token::SetFlags(vec![('i', true)]),
+            Token::OpenNonCapturingGroup(vec![('s', true), ('m', false), ('x', false)]),
+            Token::SetFlags(vec![('u', false)]),
+        ];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next().unwrap(), expected);
+        }
+        assert_eq!(lexer.next(), None);
+
+        for invalid in ["(?)", "(?q)", "(?i-m-s)", "(?ii)", "(?i"] {
+            assert!(matches!(Lexer::new(invalid).next(), Some(Token::Error(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_extended() {
+        let mut lexer = Lexer::new("a b # comment\n\\ \\#");
+        lexer.set_extended(true);
+        assert_eq!(lexer.collect::<Vec<Token>>(), vec![Token::Symbol('a'), Token::Symbol('b'), Token::Symbol(' '), Token::Symbol('#')]);
+    }
 }
 
@@ -1,3 +1,6 @@
 mod lexer;
 mod parser;
+mod set;
+mod unicode;
+mod unicode_tables;
 pub mod regex;
\ No newline at end of file
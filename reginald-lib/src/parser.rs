@@ -1,6 +1,6 @@
 use std::{fmt::Display, vec};
 
-use crate::lexer::{Lexer, Token, SetSymbol};
+use crate::lexer::{Lexer, Token, SetSymbol, Assertion};
 
 #[derive(Debug)]
 pub enum SyntaxType {
@@ -9,12 +9,22 @@ pub enum SyntaxType {
     OneOrMore,
     Once,
     Or,
+    Exactly(usize),
     From(usize),
     To(usize),
     Between(usize, usize),
+    LazyZeroOrMore,
+    LazyOptional,
+    LazyOneOrMore,
+    LazyFrom(usize),
+    LazyTo(usize),
+    LazyBetween(usize, usize),
     Symbol(char),
     Set(Vec<SetSymbol>),
     NotSet(Vec<SetSymbol>),
+    Assertion(Assertion),
+    Group(usize),
+    NamedGroup(usize, String),
     Any,
 }
 
@@ -27,12 +37,22 @@ impl SyntaxType {
             SyntaxType::OneOrMore => "OneOrMore".to_string(),
             SyntaxType::Once => "Once".to_string(),
             SyntaxType::Or => "Or".to_string(),
+            SyntaxType::Exactly(count) => format!("Exactly {}", count),
             SyntaxType::From(min) => format!("From {}", min),
             SyntaxType::To(max) => format!("To {}", max),
             SyntaxType::Between(min, max) => format!("Between {} and {}", min, max),
+            SyntaxType::LazyZeroOrMore => "Lazy ZeroOrMore".to_string(),
+            SyntaxType::LazyOptional => "Lazy Optional".to_string(),
+            SyntaxType::LazyOneOrMore => "Lazy OneOrMore".to_string(),
+            SyntaxType::LazyFrom(min) => format!("Lazy From {}", min),
+            SyntaxType::LazyTo(max) => format!("Lazy To {}", max),
+            SyntaxType::LazyBetween(min, max) => format!("Lazy Between {} and {}", min, max),
             SyntaxType::Symbol(char) => format!("Symbol {}", char),
             SyntaxType::Set(set) => set.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", "),
             SyntaxType::NotSet(set) => format!("not {}",set.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", ")),
+            SyntaxType::Assertion(assertion) => assertion.to_string(),
+            SyntaxType::Group(group) => format!("Group {}", group),
+            SyntaxType::NamedGroup(group, name) => format!("Group {} {}", group, name),
             SyntaxType::Any => "Any".to_string(),
         }
     }
@@ -47,9 +67,44 @@ L(R1R2) = L(R1)L(R2)
 L(R*) = L(R*) = {∈} U L(R) U L(R) U L(R)...
 */
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Flags {
+    /// "i", symbols and sets match regardless of case
+    pub case_insensitive: bool,
+    /// "m", "^" and "$" match at the start and end of lines instead of only at the start and end of the input
+    pub multi_line: bool,
+    /// "s", "." also matches new lines
+    pub dot_matches_new_line: bool,
+    /// "x", whitespace and comments starting with "#" are ignored
+    pub ignore_whitespace: bool,
+    /// "u", "\\b" and "\\B" use the unicode definition of a word character instead of the ascii one
+    pub unicode: bool,
+}
+
+impl Flags {
+    pub fn set(&mut self, flag: char, enabled: bool) {
+        match flag {
+            'i' => self.case_insensitive = enabled,
+            'm' => self.multi_line = enabled,
+            's' => self.dot_matches_new_line = enabled,
+            'x' => self.ignore_whitespace = enabled,
+            'u' => self.unicode = enabled,
+            _ => unreachable!(),
+        }
+    }
+
+    fn with(mut self, changes: &[(char, bool)]) -> Flags {
+        for (flag, enabled) in changes {
+            self.set(*flag, *enabled);
+        }
+        self
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseError {
     msg: String,
+    fatal: bool,
 }
 
 impl Display for ParseError {
@@ -60,13 +115,24 @@ impl Display for ParseError {
 
 impl ParseError {
     pub fn new(msg: &str) -> ParseError {
-        ParseError { msg: msg.to_string() }
+        ParseError { msg: msg.to_string(), fatal: false }
+    }
+
+    /// An error that stops the parser from backtracking and trying other branches
+    pub fn fatal(msg: &str) -> ParseError {
+        ParseError { msg: msg.to_string(), fatal: true }
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        self.fatal
     }
 }
 
 pub struct AST {
     pub nodes: Box<Vec<ASTNode>>,
     pub start_node: usize,
+    /// The name of every capture group, including the implicit group 0
+    pub groups: Vec<Option<String>>,
 }
 
 impl AST {
@@ -137,6 +203,8 @@ impl AST {
 pub struct ASTNode {
     pub node_type: SyntaxType,
     pub children: Vec<usize>,
+    /// The flags in effect where the node was parsed
+    pub flags: Flags,
 }
 
 fn push_node(nodes: &mut Box<Vec<ASTNode>>, node: ASTNode) -> usize{
@@ -144,6 +212,33 @@ fn push_node(nodes: &mut Box<Vec<ASTNode>>, node: ASTNode) -> usize{
     nodes.len() - 1
 }
 
+fn validate_set(set: &[SetSymbol]) -> Result<(), ParseError> {
+    for symbol in set {
+        match symbol {
+            SetSymbol::Range(start, end) if start > end => {
+                return Err(ParseError::fatal("the numeric value of start must be less than end in a range"))
+            },
+            SetSymbol::Set(set) | SetSymbol::NotSet(set) => validate_set(set)?,
+            SetSymbol::Operation(_, lhs, rhs) => {
+                validate_set(lhs)?;
+                validate_set(rhs)?;
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn peek(lexer: &mut Lexer, flags: Flags) -> Option<Token> {
+    lexer.set_extended(flags.ignore_whitespace);
+    lexer.peek()
+}
+
+fn next(lexer: &mut Lexer, flags: Flags) -> Option<Token> {
+    lexer.set_extended(flags.ignore_whitespace);
+    lexer.next()
+}
+
 fn create_fallback(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> (usize, usize) {
     (lexer.pos(), nodes.len())
 }
@@ -154,178 +249,223 @@ fn use_fallback(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, fallback: (usi
     nodes.truncate(len);
 }
 
-pub fn parse_regex(lexer: &mut Lexer) -> Result<AST, ParseError> {
+pub fn parse_regex(lexer: &mut Lexer, flags: Flags) -> Result<AST, ParseError> {
     let mut nodes = Box::new(vec![]);
+    let mut flags = flags;
     
-    let start_node = parse_regex_helper(lexer, &mut nodes)?;
+    let start_node = parse_regex_helper(lexer, &mut nodes, &mut flags)?;
 
-    if let Some(_) = lexer.peek() {
+    if peek(lexer, flags).is_some() {
         Err(ParseError::new("unknown symbol"))
     } else {
-        Ok(AST{nodes, start_node})
+        let groups = number_groups(&mut nodes, start_node)?;
+        Ok(AST{nodes, start_node, groups})
     }
 }
 
-fn parse_regex_helper(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> Result<usize, ParseError> {
-    if let Ok(child) = parse_or(lexer, nodes) {
-        Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Once, children: vec![child]}))
-    } else {
-        parse_concat(lexer, nodes)
+/// Numbers capture groups in the order of their opening parenthesis, returning the names of the groups.
+/// This is done after parsing, as backtracking can throw away groups that have already been parsed.
+fn number_groups(nodes: &mut [ASTNode], start_node: usize) -> Result<Vec<Option<String>>, ParseError> {
+    let mut groups = vec![None];
+    let mut stack = vec![start_node];
+
+    while let Some(node) = stack.pop() {
+        let node = &mut nodes[node];
+        match &mut node.node_type {
+            SyntaxType::Group(group) => {
+                *group = groups.len();
+                groups.push(None);
+            },
+            SyntaxType::NamedGroup(group, name) => {
+                if groups.contains(&Some(name.clone())) {
+                    return Err(ParseError::fatal(&format!("duplicate capture group name '{}'", name)))
+                }
+                *group = groups.len();
+                groups.push(Some(name.clone()));
+            },
+            _ => (),
+        }
+        stack.extend(node.children.iter().rev());
     }
+
+    Ok(groups)
 }
 
-fn parse_or(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> Result<usize, ParseError> {
+/// Inline flags last until the end of the enclosing group, so flags is updated with any set while parsing
+fn parse_regex_helper(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, flags: &mut Flags) -> Result<usize, ParseError> {
+    let mut or_flags = *flags;
+    match parse_or(lexer, nodes, &mut or_flags) {
+        Ok(child) => {
+            *flags = or_flags;
+            Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Once, children: vec![child], flags: *flags}))
+        },
+        Err(err) if err.is_fatal() => Err(err),
+        Err(_) => parse_concat(lexer, nodes, flags),
+    }
+}
+
+fn parse_or(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, flags: &mut Flags) -> Result<usize, ParseError> {
     let fallback = create_fallback(lexer, nodes);
 
-    let mut children = vec![parse_concat(lexer, nodes)?];
+    let mut children = vec![parse_concat(lexer, nodes, flags)?];
 
-    while let Some(Token::Or) =  lexer.peek() {
-        lexer.next();
-        children.push(parse_concat(lexer, nodes)?);
+    while let Some(Token::Or) = peek(lexer, *flags) {
+        next(lexer, *flags);
+        children.push(parse_concat(lexer, nodes, flags)?);
     }
 
     if children.len() == 1 {
         use_fallback(lexer, nodes, fallback);
         Err(ParseError::new("expected or"))
     } else {
-        Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Or, children}))
+        Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Or, children, flags: *flags}))
     }
 }
 
-fn parse_concat(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> Result<usize, ParseError> {
-    let mut children = vec![parse_value(lexer, nodes)?];
+fn parse_concat(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, flags: &mut Flags) -> Result<usize, ParseError> {
+    let mut children = vec![];
+
+    loop {
+        if let Some(Token::SetFlags(changes)) = peek(lexer, *flags) {
+            next(lexer, *flags);
+            *flags = flags.with(&changes);
+            continue;
+        }
 
-    while let Ok(child) = parse_value(lexer, nodes) {
-        children.push(child);
+        match parse_value(lexer, nodes, *flags) {
+            Ok(child) => children.push(child),
+            Err(err) if err.is_fatal() => return Err(err),
+            Err(_) => break,
+        }
     }
 
-    Ok(push_node(nodes, ASTNode { node_type: SyntaxType::Once, children }))
+    // a concatenation with no children is the empty regex, which only matches the empty string
+    Ok(push_node(nodes, ASTNode { node_type: SyntaxType::Once, children, flags: *flags }))
 }
 
-fn parse_value(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> Result<usize, ParseError> {
+fn parse_value(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, flags: Flags) -> Result<usize, ParseError> {
     let fallback = create_fallback(lexer, nodes);
 
-    let mut regex = parse_symbol(lexer, nodes)
-    .or_else(|_| {
+    let mut regex = parse_symbol(lexer, nodes, flags)
+    .or_else(|err| {
+        if err.is_fatal() {
+            return Err(err)
+        }
         use_fallback(lexer, nodes, fallback);
-        parse_bracketed(lexer, nodes)
+        parse_bracketed(lexer, nodes, flags)
     })?;
 
 
-    let next_token = lexer.peek();
-    if let Some(next_token) = next_token {
-        match next_token {
-            Token::ZeroOrMore => {
-                lexer.next();
-                regex = push_node(nodes, ASTNode{node_type:SyntaxType::ZeroOrMore, children: vec![regex]})
-            },
-            Token::Optional => {
-                lexer.next();
-                regex = push_node(nodes, ASTNode{node_type:SyntaxType::Optional, children: vec![regex]})
-            },
-            Token::OneOrMore => {
-                lexer.next();
-                regex = push_node(nodes, ASTNode{node_type:SyntaxType::OneOrMore, children: vec![regex]})
-            },
-            Token::From(num) => {
-                lexer.next();
-                regex = push_node(nodes, ASTNode{node_type:SyntaxType::From(num), children: vec![regex]})
-            },
-            Token::To(num) => {
-                lexer.next();
-                if num == 0 {
-                    return Err(ParseError::new("to must be greater than 0 in range"))
-                }
-                regex = push_node(nodes, ASTNode{node_type:SyntaxType::To(num), children: vec![regex]})
-            },
-            Token::Between(from, to) => {
-                lexer.next();
-                if from > to {
-                    return Err(ParseError::new("from must be lower or equal to to in range"))
-                } else if to == 0 {
-                    return Err(ParseError::new("to must be greater than 0 in range"))
-                }
-                regex = push_node(nodes, ASTNode{node_type:SyntaxType::Between(from, to), children: vec![regex]})
-            },
-            _ => (),
+    let next_token = peek(lexer, flags);
+    let repetition = match next_token {
+        Some(Token::ZeroOrMore) => Some(SyntaxType::ZeroOrMore),
+        Some(Token::Optional) => Some(SyntaxType::Optional),
+        Some(Token::OneOrMore) => Some(SyntaxType::OneOrMore),
+        Some(Token::Exactly(num)) => Some(SyntaxType::Exactly(num)),
+        Some(Token::From(num)) => Some(SyntaxType::From(num)),
+        Some(Token::To(num)) => Some(SyntaxType::To(num)),
+        Some(Token::Between(from, to)) => Some(SyntaxType::Between(from, to)),
+        Some(Token::LazyZeroOrMore) => Some(SyntaxType::LazyZeroOrMore),
+        Some(Token::LazyOptional) => Some(SyntaxType::LazyOptional),
+        Some(Token::LazyOneOrMore) => Some(SyntaxType::LazyOneOrMore),
+        Some(Token::LazyFrom(num)) => Some(SyntaxType::LazyFrom(num)),
+        Some(Token::LazyTo(num)) => Some(SyntaxType::LazyTo(num)),
+        Some(Token::LazyBetween(from, to)) => Some(SyntaxType::LazyBetween(from, to)),
+        _ => None,
+    };
+
+    if let Some(repetition) = repetition {
+        next(lexer, flags);
+        if let SyntaxType::Between(from, to) | SyntaxType::LazyBetween(from, to) = repetition {
+            if from > to {
+                return Err(ParseError::fatal("from must be lower or equal to to in range"))
+            }
         }
+        regex = push_node(nodes, ASTNode{node_type:repetition, children: vec![regex], flags})
     }
 
     Ok(regex)
 }
 
-fn parse_bracketed(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> Result<usize, ParseError> {
+fn parse_bracketed(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, flags: Flags) -> Result<usize, ParseError> {
     let fallback = create_fallback(lexer, nodes);
 
-    if let Some(Token::OpenParenthesis) = lexer.peek() {
-        lexer.next();
-    } else {
-        return Err(ParseError::new("expected parenthesis"))
-    }
-
-    let res = parse_regex_helper(lexer, nodes);
-    if res.is_ok() { 
-        if let Some(Token::CloseParenthesis) = lexer.next() {
-            res
+    let (group, mut inner_flags) = match peek(lexer, flags) {
+        Some(Token::OpenParenthesis) => (Some(SyntaxType::Group(0)), flags),
+        Some(Token::OpenNamedGroup(name)) => (Some(SyntaxType::NamedGroup(0, name)), flags),
+        Some(Token::OpenNonCapturingGroup(changes)) => (None, flags.with(&changes)),
+        _ => return Err(ParseError::new("expected parenthesis")),
+    };
+    next(lexer, flags);
+
+    let res = parse_regex_helper(lexer, nodes, &mut inner_flags);
+    match res {
+        Ok(child) => if let Some(Token::CloseParenthesis) = next(lexer, inner_flags) {
+            match group {
+                Some(group) => Ok(push_node(nodes, ASTNode{node_type: group, children: vec![child], flags})),
+                None => Ok(child),
+            }
         } else {
             use_fallback(lexer, nodes, fallback);
             Err(ParseError::new("expected regex"))
-        }
-    } else {
-        use_fallback(lexer, nodes, fallback);
-        Err(ParseError::new("expected closing parenthesis"))
+        },
+        Err(err) if err.is_fatal() => Err(err),
+        Err(_) => {
+            use_fallback(lexer, nodes, fallback);
+            Err(ParseError::new("expected closing parenthesis"))
+        },
     }
 }
 
-fn parse_symbol(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>) -> Result<usize, ParseError> {
-    if let Some(token) = lexer.peek() {
+fn parse_symbol(lexer: &mut Lexer, nodes: &mut Box<Vec<ASTNode>>, flags: Flags) -> Result<usize, ParseError> {
+    if let Some(token) = peek(lexer, flags) {
         match token {
             Token::Symbol(c) => {
-                lexer.next();
-                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Symbol(c), children: vec![]}))
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Symbol(c), children: vec![], flags}))
             },
             Token::Set(set) => {
-                for symbol in &set {
-                    if let SetSymbol::Range(start, end) = symbol {
-                        if *start >= '0' as u32 && *start <= 'z'  as u32 {
-                            if *end >= '0' as u32 && *end <= 'z'  as u32  {
-                                if  start > end{
-                                    return Err(ParseError::new("the numeric value of start must be less than end in a range"))
-                                }
-                            } else {
-                                return Err(ParseError::new("the start and end of a range must a alphanumeric"))
-                            }
-                        } else {
-                            return Err(ParseError::new("the start and end of a range must a alphanumeric"))
-                        }
-                    }
-                }
-                lexer.next();
-                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Set(set), children: vec![]}))
+                validate_set(&set)?;
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Set(set), children: vec![], flags}))
             },
             Token::NotSet(set) => {
-                for symbol in &set {
-                    if let SetSymbol::Range(start, end) = symbol {
-                        if *start >= '0' as u32 && *start <= 'z'  as u32 {
-                            if *end >= '0' as u32 && *end <= 'z'  as u32  {
-                                if  start > end{
-                                    return Err(ParseError::new("the numeric value of start must be less than end in a range"))
-                                }
-                            } else {
-                                return Err(ParseError::new("the start and end of a range must a alphanumeric"))
-                            }
-                        } else {
-                            return Err(ParseError::new("the start and end of a range must a alphanumeric"))
-                        }
-                    }
-                }
-                lexer.next();
-                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::NotSet(set), children: vec![]}))
+                validate_set(&set)?;
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::NotSet(set), children: vec![], flags}))
+            },
+            Token::Class(class) => {
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Set(vec![SetSymbol::Class(class)]), children: vec![], flags}))
+            },
+            Token::NotClass(class) => {
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::NotSet(vec![SetSymbol::Class(class)]), children: vec![], flags}))
+            },
+            Token::Property(property) => {
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Set(vec![SetSymbol::Property(property)]), children: vec![], flags}))
+            },
+            Token::NotProperty(property) => {
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::NotSet(vec![SetSymbol::Property(property)]), children: vec![], flags}))
+            },
+            Token::Assertion(assertion) => {
+                next(lexer, flags);
+                let assertion = match assertion {
+                    Assertion::StartLine if !flags.multi_line => Assertion::StartText,
+                    Assertion::EndLine if !flags.multi_line => Assertion::EndText,
+                    Assertion::WordBoundary if flags.unicode => Assertion::UnicodeWordBoundary,
+                    Assertion::NotWordBoundary if flags.unicode => Assertion::NotUnicodeWordBoundary,
+                    assertion => assertion,
+                };
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Assertion(assertion), children: vec![], flags}))
             },
             Token::Any => {
-                lexer.next();
-                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Any, children: vec![]}))
+                next(lexer, flags);
+                Ok(push_node(nodes, ASTNode{node_type: SyntaxType::Any, children: vec![], flags}))
             },
+            Token::Error(msg) => Err(ParseError::fatal(&msg)),
             _ => Err(ParseError::new("expected symbol"))
         }
     } else {
@@ -338,29 +478,72 @@ mod tests {
     use std::io::prelude::*;
 
     use crate::lexer::Lexer;
-    use super::parse_regex;
+    use super::{parse_regex, Flags};
 
     #[test]
     fn output_diagram() {
         let mut file = File::create("ast-compiled.md").unwrap();
-        writeln!(&mut file, "{}", parse_regex(&mut Lexer::new("(ab)|(cd)")).unwrap().to_string(false)).unwrap();
+        writeln!(&mut file, "{}", parse_regex(&mut Lexer::new("(ab)|(cd)"), Flags::default()).unwrap().to_string(false)).unwrap();
     }
 
 
     #[test]
     fn test() {
         assert_eq!(parse("abcd"), "((Symbol('a'))(Symbol('b'))(Symbol('c'))(Symbol('d'))Once)");
-        assert_eq!(parse("(ab)cd"), "(((Symbol('a'))(Symbol('b'))Once)(Symbol('c'))(Symbol('d'))Once)");
+        assert_eq!(parse("(ab)cd"), "((((Symbol('a'))(Symbol('b'))Once)Group(1))(Symbol('c'))(Symbol('d'))Once)");
         assert_eq!(parse("a+c*d+e?"), "(((Symbol('a'))OneOrMore)((Symbol('c'))ZeroOrMore)((Symbol('d'))OneOrMore)((Symbol('e'))Optional)Once)");
         assert_eq!(parse("a{1,}c{,1}d{2,3}"), "(((Symbol('a'))From(1))((Symbol('c'))To(1))((Symbol('d'))Between(2, 3))Once)");
+        assert_eq!(parse("a{3}b{0}?"), "(((Symbol('a'))Exactly(3))((Symbol('b'))Exactly(0))Once)");
+        assert_eq!(parse("a{0,}b{,0}c{0,0}"), "(((Symbol('a'))From(0))((Symbol('b'))To(0))((Symbol('c'))Between(0, 0))Once)");
+        assert_eq!(parse("a*?b+?c??"), "(((Symbol('a'))LazyZeroOrMore)((Symbol('b'))LazyOneOrMore)((Symbol('c'))LazyOptional)Once)");
+        assert_eq!(parse("a{1,}?c{,1}?d{2,3}?"), "(((Symbol('a'))LazyFrom(1))((Symbol('c'))LazyTo(1))((Symbol('d'))LazyBetween(2, 3))Once)");
         assert_eq!(parse("[ab-z][^ab-z]"), "((Set([Char('a'), Range(98, 122)]))(NotSet([Char('a'), Range(98, 122)]))Once)");
-        assert_eq!(parse("(ab)*cd+"), "((((Symbol('a'))(Symbol('b'))Once)ZeroOrMore)(Symbol('c'))((Symbol('d'))OneOrMore)Once)");
+        assert_eq!(parse("(ab)*cd+"), "(((((Symbol('a'))(Symbol('b'))Once)Group(1))ZeroOrMore)(Symbol('c'))((Symbol('d'))OneOrMore)Once)");
         assert_eq!(parse("ab|cd"), "((((Symbol('a'))(Symbol('b'))Once)((Symbol('c'))(Symbol('d'))Once)Or)Once)");
-        assert_eq!(parse("(a)+b|c*d"), "((((((Symbol('a'))Once)OneOrMore)(Symbol('b'))Once)(((Symbol('c'))ZeroOrMore)(Symbol('d'))Once)Or)Once)");
+        assert_eq!(parse("(a)+b|c*d"), "(((((((Symbol('a'))Once)Group(1))OneOrMore)(Symbol('b'))Once)(((Symbol('c'))ZeroOrMore)(Symbol('d'))Once)Or)Once)");
+        assert_eq!(parse("((a)|(b))(c)"), "((((((((Symbol('a'))Once)Group(2))Once)((((Symbol('b'))Once)Group(3))Once)Or)Once)Group(1))(((Symbol('c'))Once)Group(4))Once)");
+        assert_eq!(parse("\\d\\W[\\s_]"), "((Set([Class(Digit)]))(NotSet([Class(Word)]))(Set([Class(Space), Char('_')]))Once)");
+        assert_eq!(parse("^a$\\A\\z\\Z"), "((Assertion(StartText))(Symbol('a'))(Assertion(EndText))(Assertion(StartText))(Assertion(EndText))(Assertion(EndTextOptionalNewline))Once)");
+        assert_eq!(parse_with_flags("^a$\\A\\z", Flags { multi_line: true, ..Flags::default() }), "((Assertion(StartLine))(Symbol('a'))(Assertion(EndLine))(Assertion(StartText))(Assertion(EndText))Once)");
+        assert_eq!(parse("\\ba\\B"), "((Assertion(WordBoundary))(Symbol('a'))(Assertion(NotWordBoundary))Once)");
+        assert_eq!(parse_with_flags("\\ba\\B", Flags { unicode: true, ..Flags::default() }), "((Assertion(UnicodeWordBoundary))(Symbol('a'))(Assertion(NotUnicodeWordBoundary))Once)");
+        assert_eq!(parse("(?<a>x)(y)(?P<b>z)"), "((((Symbol('x'))Once)NamedGroup(1, \"a\"))(((Symbol('y'))Once)Group(2))(((Symbol('z'))Once)NamedGroup(3, \"b\"))Once)");
+        assert_eq!(parse("(?:ab)c(?i)d"), "(((Symbol('a'))(Symbol('b'))Once)(Symbol('c'))(Symbol('d'))Once)");
+        assert_eq!(parse(""), "(Once)");
+        assert_eq!(parse("()"), "(((Once)Group(1))Once)");
+        assert_eq!(parse("(a|)"), "((((((Symbol('a'))Once)(Once)Or)Once)Group(1))Once)");
+        assert_eq!(parse("(|b)"), "(((((Once)((Symbol('b'))Once)Or)Once)Group(1))Once)");
+        assert_eq!(parse("a||b"), "((((Symbol('a'))Once)(Once)((Symbol('b'))Once)Or)Once)");
+        assert_eq!(parse("\\(a\\)\\*"), "((Symbol('('))(Symbol('a'))(Symbol(')'))(Symbol('*'))Once)");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_error("a\\q"), "unknown escape sequence \\q");
+        assert_eq!(parse_error("(a|b\\q)"), "unknown escape sequence \\q");
+        assert_eq!(parse_error("([a\\q])*"), "unknown escape sequence \\q");
+        assert_eq!(parse_error("(?<a>x)|(?P<a>y)"), "duplicate capture group name 'a'");
+        assert_eq!(parse_error("(?<1>x)"), "invalid capture group name '1'");
+        assert_eq!(parse_error("a(?q)"), "unknown flag 'q'");
+        assert_eq!(parse_error("a{3,1}"), "from must be lower or equal to to in range");
+        assert_eq!(parse_error("a{3,1}?"), "from must be lower or equal to to in range");
+        assert_eq!(parse_error("[z-a]"), "the numeric value of start must be less than end in a range");
+        assert_eq!(parse_error("[^ω-α]"), "the numeric value of start must be less than end in a range");
     }
 
     fn parse(string:&str) -> String {
-        parse_regex(&mut Lexer::new(string)).unwrap().to_string(true)
+        parse_regex(&mut Lexer::new(string), Flags::default()).unwrap().to_string(true)
+    }
+
+    fn parse_with_flags(string:&str, flags: Flags) -> String {
+        parse_regex(&mut Lexer::new(string), flags).unwrap().to_string(true)
+    }
+
+    fn parse_error(string:&str) -> String {
+        match parse_regex(&mut Lexer::new(string), Flags::default()) {
+            Ok(_) => panic!("expected {} to fail", string),
+            Err(err) => err.to_string(),
+        }
     }
 }
 
@@ -1,13 +1,15 @@
-use std::{mem::swap, collections::HashSet};
+use std::{mem::swap, collections::{HashMap, BinaryHeap}, sync::Arc, ops::Range, borrow::Cow, cmp::Reverse};
 
-use crate::{parser::{parse_regex, ParseError, SyntaxType, AST}, lexer::{Lexer, SetSymbol}};
+use crate::{parser::{parse_regex, ParseError, SyntaxType, AST, Flags}, lexer::{Lexer, SetSymbol, Assertion}, set::CharSet, unicode::case_variants};
 
 #[derive(Debug)]
 enum StateType {
     Symbol(char),
     Any,
-    Set(Vec<SetSymbol>),
-    NotSet(Vec<SetSymbol>),
+    AnyExceptNewLine,
+    Set(CharSet),
+    Assertion(Assertion),
+    Save(usize),
     Accept,
     None,
 }
@@ -19,10 +21,28 @@ impl StateType {
             StateType::Accept => "Accept".to_string(),
             StateType::None => "None".to_string(),
             StateType::Any => "Any".to_string(),
-            StateType::Set(set) => set.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", "),
-            StateType::NotSet(set) => format!("not {}",set.iter().map(|symbol| symbol.to_string()).collect::<Vec<String>>().join(", ")),
+            StateType::AnyExceptNewLine => "Any except new line".to_string(),
+            StateType::Set(set) => set.ranges().iter().map(|(start, end)| format!("c{}-c{}", start, end)).collect::<Vec<String>>().join(", "),
+            StateType::Assertion(assertion) => assertion.to_string(),
+            StateType::Save(slot) => format!("Save {}", slot),
         }
     }
+
+    /// Whether a thread in this state steps over c, the char at its position or None at the end of the haystack
+    fn consumes(&self, c: Option<char>) -> bool {
+        match self {
+            StateType::Symbol(symbol) => c == Some(*symbol),
+            StateType::Any => c.is_some(),
+            StateType::AnyExceptNewLine => c.is_some_and(|c| c != '\n'),
+            StateType::Set(set) => c.is_some_and(|c| set.contains(c)),
+            StateType::Assertion(_) | StateType::Save(_) | StateType::Accept | StateType::None => false,
+        }
+    }
+
+    /// Whether threads in this state have their own slots, the other states are only passed through by `Regex::add_thread`
+    fn has_slots(&self) -> bool {
+        !matches!(self, StateType::Assertion(_) | StateType::Save(_) | StateType::None)
+    }
 }
 
 struct States {
@@ -57,167 +77,894 @@ struct StateNode {
     next: Vec<usize>
 }
 
-pub struct Regex {
-    states: States,
+/// Which match is reported when there is more than one match starting at the same position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchKind {
+    /// The match that is found first, following the order of alternatives and the greediness of repetitions, like Perl
+    #[default]
+    LeftmostFirst,
+    /// The longest match, like POSIX
+    LeftmostLongest,
 }
 
-impl Regex {
-    pub fn compile(code: &str) -> Result<Regex, ParseError> {
-        let ast = parse_regex(&mut Lexer::new(&code))?;
-        let mut regex = Regex{states: States { nodes: Box::new(vec![StateNode{state_type: StateType::None, next: vec![]}]), starting_state: 0 }};
+pub struct RegexBuilder {
+    code: String,
+    flags: Flags,
+    strict: bool,
+    match_kind: MatchKind,
+}
+
+impl RegexBuilder {
+    pub fn new(code: &str) -> RegexBuilder {
+        RegexBuilder { code: code.to_string(), flags: Flags::default(), strict: false, match_kind: MatchKind::default() }
+    }
+
+    /// Which match is reported when there is more than one match starting at the same position, defaults to `MatchKind::LeftmostFirst`
+    pub fn match_kind(&mut self, kind: MatchKind) -> &mut RegexBuilder {
+        self.match_kind = kind;
+        self
+    }
+
+    /// When enabled a "{" that does not start a valid repetition is an error, instead of matching a literal "{"
+    pub fn strict(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.strict = yes;
+        self
+    }
+
+    /// When enabled letters will match both their upper and lower case forms
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.case_insensitive = yes;
+        self
+    }
+
+    /// When enabled "^" and "$" will match at the start and end of every line
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.multi_line = yes;
+        self
+    }
+
+    /// When enabled "." will also match new lines
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.dot_matches_new_line = yes;
+        self
+    }
+
+    /// When enabled whitespace in the regex is ignored and "#" starts a comment that lasts until the end of the line
+    pub fn ignore_whitespace(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.ignore_whitespace = yes;
+        self
+    }
+
+    /// When enabled "\\b" and "\\B" will treat any unicode letter or number as a word character, not just ascii ones
+    pub fn unicode(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.flags.unicode = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, ParseError> {
+        let mut lexer = Lexer::new(&self.code);
+        lexer.set_strict(self.strict);
+        let ast = parse_regex(&mut lexer, self.flags)?;
+        let names = ast.groups.iter().enumerate()
+            .filter_map(|(group, name)| name.clone().map(|name| (name, group)))
+            .collect();
+        let mut regex = Regex{
+            states: States { nodes: Box::new(vec![StateNode{state_type: StateType::None, next: vec![]}]), starting_state: 0 },
+            groups: ast.groups.clone(),
+            names: Arc::new(names),
+            match_kind: self.match_kind,
+        };
 
         regex.init(ast);
 
         Ok(regex)
     }
+}
+
+/// A single match in a string, its offsets are in bytes so it can be used to slice the string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'t> {
+    haystack: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    fn new(haystack: &'t str, start: usize, end: usize) -> Match<'t> {
+        Match { haystack, start, end }
+    }
+
+    /// The byte offset of the start of the match
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The byte offset just after the end of the match
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// The matched part of the string
+    pub fn as_str(&self) -> &'t str {
+        &self.haystack[self.range()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// The spans of every capture group from a single match. Group 0 is always the whole match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures<'t> {
+    haystack: &'t str,
+    slots: Vec<Option<usize>>,
+    names: Arc<HashMap<String, usize>>,
+}
 
-    pub fn test(&self, string: &str) -> bool {
-        let chars = string.chars().collect();
-        return self.simulate_states(&chars, 0) == chars.len();
+impl<'t> Captures<'t> {
+    /// A capture group, or None if the group did not participate in the match
+    pub fn get(&self, group: usize) -> Option<Match<'t>> {
+        match (self.slots.get(group * 2)?, self.slots.get(group * 2 + 1)?) {
+            (Some(start), Some(end)) => Some(Match::new(self.haystack, *start, *end)),
+            _ => None,
+        }
     }
 
-    pub fn matches(&self, string: &str) -> Vec<(usize, usize)> {
-        let chars = &string.chars().collect();
-        let mut found = vec![];
-        let mut i = 0usize;
+    /// A named capture group, or None if there is no such group or it did not participate in the match
+    pub fn name(&self, name: &str) -> Option<Match<'t>> {
+        self.get(*self.names.get(name)?)
+    }
 
+    /// Every capture group in order, None for groups that did not participate in the match
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'t>>> + '_ {
+        (0..self.slots.len() / 2).map(|group| self.get(group))
+    }
 
-        while i < string.len() {
-            let size_of_found = self.simulate_states(chars, i);
-            if size_of_found != 0 {
-                found.push((i, size_of_found));
-                i += size_of_found
+    /// Appends template to dst, replacing "$n" and "${n}" with capture group n, "${name}" with a named group and "$$" with "$"
+    ///
+    /// Groups that do not exist or did not participate in the match are replaced with nothing.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+            } else if let Some((name, after)) = rest.strip_prefix('{').and_then(|braced| braced.split_once('}')) {
+                dst.push_str(self.reference(name).map_or("", |found| found.as_str()));
+                rest = after;
             } else {
-                i += 1
+                let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                if digits == 0 {
+                    dst.push('$');
+                } else {
+                    dst.push_str(self.reference(&rest[..digits]).map_or("", |found| found.as_str()));
+                }
+                rest = &rest[digits..];
             }
         }
+        dst.push_str(rest);
+    }
 
-        found
+    /// A capture group by its number or its name
+    fn reference(&self, name: &str) -> Option<Match<'t>> {
+        match name.parse::<usize>() {
+            Ok(group) => self.get(group),
+            Err(_) => self.name(name),
+        }
     }
+}
 
-    pub fn is_match(&self, string: &str) -> Option<(usize, usize)> {
-        let chars = &string.chars().collect();
-        let mut i = 0usize;
+/// Builds the replacement for a match, see `Regex::replace`
+pub trait Replacer {
+    /// Appends the replacement for a match to dst
+    fn replace_append(&mut self, captures: &Captures, dst: &mut String);
+}
 
+/// A template, see `Captures::expand`
+impl Replacer for &str {
+    fn replace_append(&mut self, captures: &Captures, dst: &mut String) {
+        captures.expand(self, dst)
+    }
+}
 
-        while i < string.len() {
-            let size_of_found = self.simulate_states(chars, i);
-            if size_of_found != 0 {
-                return Some((i, size_of_found))
-            } else {
-                i += 1
+impl<F> Replacer for F
+where
+    F: FnMut(&Captures) -> String,
+{
+    fn replace_append(&mut self, captures: &Captures, dst: &mut String) {
+        dst.push_str(&self(captures))
+    }
+}
+
+/// An ordered set of states, used to keep track of the threads of the state machine in priority order
+struct SparseSet {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    fn new(size: usize) -> SparseSet {
+        SparseSet { dense: Vec::with_capacity(size), sparse: vec![0; size] }
+    }
+
+    fn contains(&self, value: usize) -> bool {
+        let index = self.sparse[value];
+        index < self.dense.len() && self.dense[index] == value
+    }
+
+    fn insert(&mut self, value: usize) -> bool {
+        if self.contains(value) {
+            return false
+        }
+        self.sparse[value] = self.dense.len();
+        self.dense.push(value);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+/// The live threads of the state machine at one position. Every thread carries the capture slots it has recorded so far.
+struct Threads {
+    set: SparseSet,
+    slots: Vec<Option<usize>>,
+    slot_count: usize,
+}
+
+impl Threads {
+    fn new(states: usize, slot_count: usize) -> Threads {
+        Threads { set: SparseSet::new(states), slots: vec![None; states * slot_count], slot_count }
+    }
+
+    fn slots(&self, state: usize) -> &[Option<usize>] {
+        &self.slots[state * self.slot_count..(state + 1) * self.slot_count]
+    }
+
+    fn slots_mut(&mut self, state: usize) -> &mut [Option<usize>] {
+        &mut self.slots[state * self.slot_count..(state + 1) * self.slot_count]
+    }
+}
+
+/// What to search and how, every search method of `Regex` accepts an `Input` or a string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input<'t> {
+    haystack: &'t str,
+    span: Range<usize>,
+    anchored: bool,
+    earliest: bool,
+}
+
+impl<'t> Input<'t> {
+    pub fn new(haystack: &'t str) -> Input<'t> {
+        Input { haystack, span: 0..haystack.len(), anchored: false, earliest: false }
+    }
+
+    /// Only search between these byte offsets, assertions like "\\b" and "$" can still see the rest of the string
+    ///
+    /// Panics if the span is out of bounds or does not start and end on a char boundary.
+    pub fn span(mut self, span: Range<usize>) -> Input<'t> {
+        assert!(
+            span.start <= span.end && self.haystack.is_char_boundary(span.start) && self.haystack.is_char_boundary(span.end),
+            "invalid span {:?} for a string of length {}", span, self.haystack.len()
+        );
+        self.span = span;
+        self
+    }
+
+    /// Only search from this byte offset onwards
+    pub fn start(self, start: usize) -> Input<'t> {
+        let end = self.span.end;
+        self.span(start..end)
+    }
+
+    /// When enabled a match must start at the start of the span, and when iterating every match must start where the last one ended
+    ///
+    /// Iterating stops after an empty match, as the next match could only start by skipping a char.
+    pub fn anchored(mut self, yes: bool) -> Input<'t> {
+        self.anchored = yes;
+        self
+    }
+
+    /// When enabled the search stops as soon as any match ends, instead of looking for the highest priority match
+    pub fn earliest(mut self, yes: bool) -> Input<'t> {
+        self.earliest = yes;
+        self
+    }
+}
+
+impl<'t> From<&'t str> for Input<'t> {
+    fn from(haystack: &'t str) -> Input<'t> {
+        Input::new(haystack)
+    }
+}
+
+impl<'t> From<&'t String> for Input<'t> {
+    fn from(haystack: &'t String) -> Input<'t> {
+        Input::new(haystack)
+    }
+}
+
+/// The scratch space of the state machine, kept between searches so it is only allocated once
+struct Cache {
+    current: Threads,
+    next: Threads,
+    stack: Vec<Frame>,
+    scratch: Vec<Option<usize>>,
+    slots: Vec<Option<usize>>,
+}
+
+impl Cache {
+    fn new(states: usize, slot_count: usize) -> Cache {
+        Cache {
+            current: Threads::new(states, slot_count),
+            next: Threads::new(states, slot_count),
+            stack: vec![],
+            scratch: vec![None; slot_count],
+            slots: vec![None; slot_count],
+        }
+    }
+}
+
+/// Which matches the state machine looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Search {
+    /// The match chosen by the match kind
+    Leftmost,
+    /// The match chosen by the match kind that ends at the end of the haystack
+    ToEnd,
+}
+
+/// A lazy iterator over every match including overlapping ones, see `Regex::find_overlapping_iter`
+pub struct OverlappingMatches<'r, 't> {
+    regex: &'r Regex,
+    input: Input<'t>,
+    cache: Cache,
+    /// The position the state machine steps over next and the lowest start a match found from there can have, None once the search is over
+    next: Option<(usize, usize)>,
+    /// Matches that have been found but could still be preceded by a match with a lower start
+    found: BinaryHeap<Reverse<(usize, usize)>>,
+}
+
+impl<'r, 't> Iterator for OverlappingMatches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            if let Some(Reverse((start, end))) = self.found.peek().copied() {
+                if self.next.is_none_or(|(_, lowest)| start <= lowest) {
+                    self.found.pop();
+                    return Some(Match::new(self.input.haystack, start, end))
+                }
             }
+
+            let (index, _) = self.next?;
+            self.next = self.regex.step_overlapping(&mut self.cache, &self.input, index, &mut self.found);
+        }
+    }
+}
+
+/// A lazy iterator over every non-overlapping match and its capture groups, see `Regex::captures_iter`
+pub struct CaptureMatches<'r, 't> {
+    regex: &'r Regex,
+    input: Input<'t>,
+    cache: Cache,
+    next: Option<usize>,
+}
+
+impl<'r, 't> Iterator for CaptureMatches<'r, 't> {
+    type Item = Captures<'t>;
+
+    fn next(&mut self) -> Option<Captures<'t>> {
+        match self.regex.captures_at(&self.input, &mut self.cache, self.next?) {
+            Some((captures, start, end)) => {
+                // an anchored search can't skip over the char after an empty match
+                self.next = if start != end {
+                    Some(end)
+                } else if self.input.anchored {
+                    None
+                } else {
+                    Some(next_char(self.input.haystack, end))
+                };
+                Some(captures)
+            },
+            None => {
+                self.next = None;
+                None
+            },
+        }
+    }
+}
+
+/// The byte offset of the char after the one at index, or one past the end of the string
+fn next_char(string: &str, index: usize) -> usize {
+    index + string[index..].chars().next().map_or(1, char::len_utf8)
+}
+
+/// A lazy iterator over every non-overlapping match, see `Regex::find_iter`
+pub struct Matches<'r, 't>(CaptureMatches<'r, 't>);
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        self.0.next().and_then(|captures| captures.get(0))
+    }
+}
+
+/// A lazy iterator over the fields between matches, see `Regex::split`
+pub struct Split<'r, 't> {
+    found: Matches<'r, 't>,
+    string: &'t str,
+    last: usize,
+    end: usize,
+    done: bool,
+}
+
+impl<'r, 't> Split<'r, 't> {
+    /// Everything after the last delimiter that has been found
+    fn rest(&mut self) -> Option<&'t str> {
+        if self.done {
+            return None
+        }
+        self.done = true;
+        Some(&self.string[self.last..self.end])
+    }
+}
+
+impl<'r, 't> Iterator for Split<'r, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        match self.found.next() {
+            Some(found) => {
+                let field = &self.string[self.last..found.start()];
+                self.last = found.end();
+                Some(field)
+            },
+            None => self.rest(),
+        }
+    }
+}
+
+/// A lazy iterator over at most limit fields between matches, see `Regex::splitn`
+pub struct SplitN<'r, 't> {
+    split: Split<'r, 't>,
+    limit: usize,
+}
+
+impl<'r, 't> Iterator for SplitN<'r, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                self.split.rest()
+            },
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            },
+        }
+    }
+}
+
+/// Either a field or the captures of the delimiter after it, see `Regex::split_with_delimiters`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitItem<'t> {
+    Field(&'t str),
+    Delimiter(Captures<'t>),
+}
+
+/// A lazy iterator over the fields between matches and the matches themselves, see `Regex::split_with_delimiters`
+pub struct SplitWithDelimiters<'r, 't> {
+    found: CaptureMatches<'r, 't>,
+    string: &'t str,
+    last: usize,
+    end: usize,
+    delimiter: Option<Captures<'t>>,
+    done: bool,
+}
+
+impl<'r, 't> Iterator for SplitWithDelimiters<'r, 't> {
+    type Item = SplitItem<'t>;
+
+    fn next(&mut self) -> Option<SplitItem<'t>> {
+        if let Some(delimiter) = self.delimiter.take() {
+            return Some(SplitItem::Delimiter(delimiter))
         }
+        if self.done {
+            return None
+        }
+
+        match self.found.next() {
+            Some(captures) => {
+                let found = captures.get(0).unwrap();
+                let field = &self.string[self.last..found.start()];
+                self.last = found.end();
+                self.delimiter = Some(captures);
+                Some(SplitItem::Field(field))
+            },
+            None => {
+                self.done = true;
+                Some(SplitItem::Field(&self.string[self.last..self.end]))
+            },
+        }
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    RestoreSlot(usize, Option<usize>),
+}
+
+pub struct Regex {
+    states: States,
+    groups: Vec<Option<String>>,
+    names: Arc<HashMap<String, usize>>,
+    match_kind: MatchKind,
+}
+
+impl Regex {
+    pub fn compile(code: &str) -> Result<Regex, ParseError> {
+        RegexBuilder::new(code).build()
+    }
+
+    /// Whether the whole string, or the whole span of the input, matches
+    pub fn test<'t, I: Into<Input<'t>>>(&self, input: I) -> bool {
+        let input = input.into();
+        self.simulate_states(&mut self.cache(), &input, input.span.start, Search::ToEnd).is_some()
+    }
 
-        return None
+    /// Every non-overlapping match
+    pub fn matches<'t, I: Into<Input<'t>>>(&self, input: I) -> Vec<Match<'t>> {
+        self.find_iter(input).collect()
+    }
+
+    /// Lazily finds every non-overlapping match
+    pub fn find_iter<'r, 't, I: Into<Input<'t>>>(&'r self, input: I) -> Matches<'r, 't> {
+        Matches(self.captures_iter(input))
+    }
+
+    /// The first match
+    pub fn is_match<'t, I: Into<Input<'t>>>(&self, input: I) -> Option<Match<'t>> {
+        self.captures(input).and_then(|captures| captures.get(0))
+    }
+
+    /// Lazily finds every match, including ones that overlap, ordered by where they start and then where they end
+    ///
+    /// Every position a match can end at is reported once, with the lowest start that can reach it, so "a+" finds "a", "aa" and "aaa" in "aaa" all starting at 0.
+    /// The string is only scanned once, matches are held back until no match with a lower start can still be found.
+    pub fn find_overlapping_iter<'r, 't, I: Into<Input<'t>>>(&'r self, input: I) -> OverlappingMatches<'r, 't> {
+        let input = input.into();
+        let start = input.span.start;
+        OverlappingMatches { regex: self, input, cache: self.cache(), next: Some((start, start)), found: BinaryHeap::new() }
+    }
+
+    /// The byte offset of the end of the first match, the search stops as soon as any match ends
+    ///
+    /// The end can be before the end of the match `Regex::is_match` would find, as the highest priority match is not looked for.
+    pub fn shortest_match<'t, I: Into<Input<'t>>>(&self, input: I) -> Option<usize> {
+        self.is_match(input.into().earliest(true)).map(|found| found.end())
+    }
+
+    /// The first match starting at or after the byte offset start, assertions can still see the string before start
+    ///
+    /// # Panics
+    ///
+    /// Panics if start is past the end of the string or is not on a char boundary.
+    pub fn find_at<'t>(&self, string: &'t str, start: usize) -> Option<Match<'t>> {
+        self.is_match(Input::new(string).start(start))
+    }
+
+    /// The first match starting at or after the byte offset start, like `Regex::is_match` it returns the match itself
+    ///
+    /// # Panics
+    ///
+    /// Panics if start is past the end of the string or is not on a char boundary.
+    pub fn is_match_at<'t>(&self, string: &'t str, start: usize) -> Option<Match<'t>> {
+        self.find_at(string, start)
+    }
+
+    /// Whether there is a match starting at or after the byte offset start
+    ///
+    /// # Panics
+    ///
+    /// Panics if start is past the end of the string or is not on a char boundary.
+    pub fn has_match_at(&self, string: &str, start: usize) -> bool {
+        self.find_at(string, start).is_some()
+    }
+
+    /// Finds the first match and the spans of its capture groups
+    pub fn captures<'t, I: Into<Input<'t>>>(&self, input: I) -> Option<Captures<'t>> {
+        let input = input.into();
+        self.captures_at(&input, &mut self.cache(), input.span.start).map(|(captures, _, _)| captures)
+    }
+
+    /// Lazily finds every non-overlapping match and the spans of their capture groups
+    ///
+    /// After an empty match the search continues one character further on, so every position is only reported once.
+    pub fn captures_iter<'r, 't, I: Into<Input<'t>>>(&'r self, input: I) -> CaptureMatches<'r, 't> {
+        let input = input.into();
+        let next = Some(input.span.start);
+        CaptureMatches { regex: self, input, cache: self.cache(), next }
+    }
+
+    /// Lazily splits the string, or the span of the input, into the fields between every non-overlapping match
+    pub fn split<'r, 't, I: Into<Input<'t>>>(&'r self, input: I) -> Split<'r, 't> {
+        let input = input.into();
+        let (string, Range { start, end }) = (input.haystack, input.span.clone());
+        Split { found: self.find_iter(input), string, last: start, end, done: false }
+    }
+
+    /// Lazily splits the string into at most limit fields, the last field is the rest of the string or span
+    pub fn splitn<'r, 't, I: Into<Input<'t>>>(&'r self, input: I, limit: usize) -> SplitN<'r, 't> {
+        SplitN { split: self.split(input), limit }
+    }
+
+    /// Lazily splits the string like `Regex::split`, but also yields the captures of every delimiter between the fields
+    pub fn split_with_delimiters<'r, 't, I: Into<Input<'t>>>(&'r self, input: I) -> SplitWithDelimiters<'r, 't> {
+        let input = input.into();
+        let (string, Range { start, end }) = (input.haystack, input.span.clone());
+        SplitWithDelimiters { found: self.captures_iter(input), string, last: start, end, delimiter: None, done: false }
+    }
+
+    /// Replaces the first match with the replacement built by replacer
+    pub fn replace<'t, I: Into<Input<'t>>, R: Replacer>(&self, input: I, replacer: R) -> Cow<'t, str> {
+        self.replacen(input, 1, replacer)
+    }
+
+    /// Replaces every non-overlapping match with the replacement built by replacer
+    pub fn replace_all<'t, I: Into<Input<'t>>, R: Replacer>(&self, input: I, replacer: R) -> Cow<'t, str> {
+        self.replacen(input, 0, replacer)
+    }
+
+    /// Replaces the first limit non-overlapping matches, or every match if limit is 0. The string is only copied if there is a match.
+    ///
+    /// Only matches within the span of the input are replaced, the whole string is still returned.
+    pub fn replacen<'t, I: Into<Input<'t>>, R: Replacer>(&self, input: I, limit: usize, mut replacer: R) -> Cow<'t, str> {
+        let input = input.into();
+        let string = input.haystack;
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut found = self.captures_iter(input).take(limit).peekable();
+        if found.peek().is_none() {
+            return Cow::Borrowed(string)
+        }
+
+        let mut replaced = String::with_capacity(string.len());
+        let mut last = 0;
+        for captures in found {
+            let whole = captures.get(0).unwrap();
+            replaced.push_str(&string[last..whole.start()]);
+            replacer.replace_append(&captures, &mut replaced);
+            last = whole.end();
+        }
+        replaced.push_str(&string[last..]);
+
+        Cow::Owned(replaced)
+    }
+
+    /// The number of capture groups, including the implicit group 0 for the whole match
+    pub fn captures_len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// The name of every capture group in order, including the implicit group 0 for the whole match
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.groups.iter().map(|name| name.as_deref())
+    }
+
+    /// The index of the capture group with the given name
+    pub fn capture_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
     }
 
     pub fn to_string(&self) -> String {
         self.states.to_string()
     }
 
+    fn slot_count(&self) -> usize {
+        self.captures_len() * 2
+    }
+
+    fn cache(&self) -> Cache {
+        Cache::new(self.states.nodes.len(), self.slot_count())
+    }
+
+    /// Finds the first match starting at or after the byte offset i, or only at i if the input is anchored, along with the byte offsets of its start and end
+    fn captures_at<'t>(&self, input: &Input<'t>, cache: &mut Cache, i: usize) -> Option<(Captures<'t>, usize, usize)> {
+        let end = self.simulate_states(cache, input, i, Search::Leftmost)?;
+        let start = cache.slots[0].unwrap();
 
-    fn simulate_states(&self, chars: &Vec<char>, offset: usize) -> usize{
-        if offset >= chars.len() {
-            return 0
+        Some((Captures { haystack: input.haystack, slots: cache.slots.clone(), names: self.names.clone() }, start, end))
+    }
+
+    /// Runs the state machine from offset, returning the end of the highest priority match and filling the slots of the cache with its captures
+    ///
+    /// Threads are kept in priority order, so once a thread accepts every lower priority thread is dropped. For leftmost longest matches they keep running instead.
+    /// If the input is earliest the first match found is returned.
+    ///
+    /// Unless the search is anchored, a new thread is started at every position until a match is found, so the string is only scanned once.
+    /// Threads that started earlier always have a higher priority, and every thread records where it started in slot 0.
+    /// The string is decoded one char at a time as the threads step over it, so positions are byte offsets.
+    fn simulate_states(&self, cache: &mut Cache, input: &Input, offset: usize, search: Search) -> Option<usize> {
+        let (string, end) = (input.haystack, input.span.end);
+        if offset > end {
+            return None
         }
 
-        let mut max_len =  offset;
-        let mut set = &mut HashSet::new();
-        set.insert((offset, self.states.starting_state));
+        let Cache { current, next, stack, scratch, slots } = cache;
+        let (mut current, mut next) = (current, next);
+        current.set.clear();
+        next.set.clear();
+        let unanchored = search == Search::Leftmost && !input.anchored;
+        let mut found = None;
+
+        let mut index = offset;
+        loop {
+            if found.is_none() && index <= end && (index == offset || unanchored) {
+                scratch.fill(None);
+                scratch[0] = Some(index);
+                self.add_thread(current, stack, scratch, string, index, self.states.starting_state);
+            }
+            if current.set.dense.is_empty() {
+                break
+            }
 
-        let mut set_back = &mut HashSet::new();
+            let c = string[index..end].chars().next();
+            let next_index = index + c.map_or(1, char::len_utf8);
 
-        while !set.is_empty() {
-            let mut stack = set.drain();
-            while let Some((index, state)) = stack.next() {
-                let state = self.states.nodes.get(state).unwrap();
+            for i in 0..current.set.dense.len() {
+                let state_index = current.set.dense[i];
+                let state = self.states.nodes.get(state_index).unwrap();
 
-                match &state.state_type {
-                    StateType::Symbol(c) => if index+1 > chars.len() {
-                        continue;
-                    } else if *c == chars[index] {
-                        for next_state in &state.next {
-                            set_back.insert((index+1, *next_state));
-                        }
-                    } else {
-                        continue;
-                    },
-                    StateType::Accept => max_len = max_len.max(index),
-                    StateType::None => for next_state in &state.next {
-                        set_back.insert((index, *next_state));
-                    }
-                    StateType::Any => if index+1 > chars.len() {
-                        continue;
-                    } else {
-                        for next_state in &state.next {
-                            set_back.insert((index+1, *next_state));
-                        }
-                    },
-                    StateType::Set(set) => if index+1 > chars.len() {
-                        continue;
-                    } else {
-                        if {
-                            let mut found = false;
-                            for symbol in set {
-                                match symbol {
-                                    SetSymbol::Char(c) => if *c == chars[index] {
-                                        found = true;
-                                        break;
-                                    },
-                                    SetSymbol::Range(start, end) => 
-                                    if chars[index] as u32 >= *start && chars[index] as u32 <= *end {
-                                        found = true;
-                                        break;
-                                    },
-                                }
-                            }
-                            found
-                        } {
-                            for next_state in &state.next {
-                                set_back.insert((index+1, *next_state));
-                            }
-                        } else {
+                // threads that started after the match that has been found can't be leftmost
+                if found.is_some() && current.slots(state_index)[0] > slots[0] {
+                    break;
+                }
+
+                let is_match = match &state.state_type {
+                    StateType::Accept => {
+                        if search == Search::ToEnd && index != end {
                             continue;
                         }
-                    },
-                    StateType::NotSet(set) => if index+1 > chars.len() {
-                        continue;
-                    } else {
-                        if {
-                            let mut found = true;
-                            for symbol in set {
-                                match symbol {
-                                    SetSymbol::Char(c) => if *c == chars[index] {
-                                        found = false;
-                                        break;
-                                    },
-                                    SetSymbol::Range(start, end) => 
-                                    if chars[index] as u32 >= *start && chars[index] as u32 <= *end {
-                                        found = false;
-                                        break;
-                                    },
-                                }
-                            }
-                            found
-                        } {
-                            for next_state in &state.next {
-                                set_back.insert((index+1, *next_state));
-                            }
-                        } else {
-                            continue;
+                        found = Some(index);
+                        slots.copy_from_slice(current.slots(state_index));
+                        match self.match_kind {
+                            MatchKind::LeftmostFirst => break,
+                            MatchKind::LeftmostLongest => continue,
                         }
                     },
+                    state_type => state_type.consumes(c),
+                };
+
+                if is_match {
+                    scratch.copy_from_slice(current.slots(state_index));
+                    for next_state in &state.next {
+                        self.add_thread(next, stack, scratch, string, next_index, *next_state);
+                    }
                 }
             }
 
-            drop(stack);
-            swap(&mut set, &mut set_back);
+            if input.earliest && found.is_some() {
+                break
+            }
+
+            current.set.clear();
+            swap(&mut current, &mut next);
+            index = next_index;
+        }
+
+        if let Some(end) = found {
+            slots[1] = Some(end);
         }
 
-        return max_len - offset
+        found
     }
 
+    /// Steps every thread of an overlapping search over the char at index, pushing every match that ends at index onto found
+    ///
+    /// Threads are merged by state, so each end is only found once, with the lowest start that can reach it. A thread is started at every position unless the input is anchored.
+    /// Returns the next position along with the lowest start any match found from there can have, or None once no more matches can be found.
+    fn step_overlapping(&self, cache: &mut Cache, input: &Input, index: usize, found: &mut BinaryHeap<Reverse<(usize, usize)>>) -> Option<(usize, usize)> {
+        let (string, end) = (input.haystack, input.span.end);
+        let Cache { current, next, stack, scratch, .. } = cache;
+
+        if index <= end && (index == input.span.start || !input.anchored) {
+            scratch.fill(None);
+            scratch[0] = Some(index);
+            self.add_thread(current, stack, scratch, string, index, self.states.starting_state);
+        }
+        if current.set.dense.is_empty() {
+            return None
+        }
+
+        let c = string[index..end].chars().next();
+        let next_index = index + c.map_or(1, char::len_utf8);
+        let mut accepted = false;
+        for &state_index in &current.set.dense {
+            let state = self.states.nodes.get(state_index).unwrap();
+            if let StateType::Accept = state.state_type {
+                found.push(Reverse((current.slots(state_index)[0].unwrap(), index)));
+                accepted = true;
+            } else if state.state_type.consumes(c) {
+                scratch.copy_from_slice(current.slots(state_index));
+                for next_state in &state.next {
+                    self.add_thread(next, stack, scratch, string, next_index, *next_state);
+                }
+            }
+        }
+
+        current.set.clear();
+        swap(current, next);
+        if input.earliest && accepted {
+            current.set.clear();
+            return None
+        }
+
+        let seeded = (next_index <= end && !input.anchored).then_some(next_index);
+        let lowest = current.set.dense.iter()
+            .filter(|state_index| self.states.nodes[**state_index].state_type.has_slots())
+            .filter_map(|state_index| current.slots(*state_index)[0])
+            .chain(seeded)
+            .min()?;
+
+        Some((next_index, lowest))
+    }
+
+    /// Adds a thread for state and follows every transition that does not consume a character
+    fn add_thread(&self, threads: &mut Threads, stack: &mut Vec<Frame>, scratch: &mut [Option<usize>], string: &str, index: usize, state: usize) {
+        stack.push(Frame::Explore(state));
+
+        while let Some(frame) = stack.pop() {
+            let state_index = match frame {
+                Frame::Explore(state_index) => state_index,
+                Frame::RestoreSlot(slot, value) => {
+                    scratch[slot] = value;
+                    continue;
+                },
+            };
+
+            if !threads.set.insert(state_index) {
+                continue;
+            }
+
+            let state = self.states.nodes.get(state_index).unwrap();
+            match &state.state_type {
+                StateType::None => (),
+                StateType::Save(slot) => {
+                    stack.push(Frame::RestoreSlot(*slot, scratch[*slot]));
+                    scratch[*slot] = Some(index);
+                },
+                StateType::Assertion(assertion) => if !assertion.is_match(string, index) {
+                    continue;
+                },
+                _ => {
+                    threads.slots_mut(state_index).copy_from_slice(scratch);
+                    continue;
+                },
+            }
+
+            for next_state in state.next.iter().rev() {
+                stack.push(Frame::Explore(*next_state));
+            }
+        }
+    }
 
     fn init(&mut self, ast: AST) {
         let end_state = self.compile_once(self.states.starting_state, &ast, ast.start_node);
@@ -231,71 +978,140 @@ impl Regex {
 
     fn compile_next(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
         match ast.nodes.get(ast_node).unwrap().node_type {
-            SyntaxType::ZeroOrMore => self.compile_zero_or_more(prev_state, ast, ast_node),
-            SyntaxType::Optional => self.compile_optional(prev_state, ast, ast_node),
-            SyntaxType::OneOrMore => self.compile_one_or_more(prev_state, ast, ast_node),
+            SyntaxType::ZeroOrMore => self.compile_zero_or_more(prev_state, ast, ast_node, true),
+            SyntaxType::Optional => self.compile_optional(prev_state, ast, ast_node, true),
+            SyntaxType::OneOrMore => self.compile_one_or_more(prev_state, ast, ast_node, true),
             SyntaxType::Once => self.compile_once(prev_state, ast, ast_node),
             SyntaxType::Or => self.compile_or(prev_state, ast, ast_node),
+            SyntaxType::Exactly(_) => self.compile_exactly(prev_state, ast, ast_node),
             SyntaxType::From(_) => self.compile_from(prev_state, ast, ast_node),
             SyntaxType::To(_) => self.compile_to(prev_state, ast, ast_node),
             SyntaxType::Between(_, _) => self.compile_between(prev_state, ast, ast_node),
+            SyntaxType::LazyZeroOrMore => self.compile_zero_or_more(prev_state, ast, ast_node, false),
+            SyntaxType::LazyOptional => self.compile_optional(prev_state, ast, ast_node, false),
+            SyntaxType::LazyOneOrMore => self.compile_one_or_more(prev_state, ast, ast_node, false),
+            SyntaxType::LazyFrom(_) => self.compile_from(prev_state, ast, ast_node),
+            SyntaxType::LazyTo(_) => self.compile_to(prev_state, ast, ast_node),
+            SyntaxType::LazyBetween(_, _) => self.compile_between(prev_state, ast, ast_node),
             SyntaxType::Symbol(_) => self.compile_atomic(prev_state, ast, ast_node),
             SyntaxType::Set(_) => self.compile_atomic(prev_state, ast, ast_node),
             SyntaxType::NotSet(_) => self.compile_atomic(prev_state, ast, ast_node),
             SyntaxType::Any => self.compile_atomic(prev_state, ast, ast_node),
+            SyntaxType::Assertion(_) => self.compile_atomic(prev_state, ast, ast_node),
+            SyntaxType::Group(_) => self.compile_group(prev_state, ast, ast_node),
+            SyntaxType::NamedGroup(_, _) => self.compile_group(prev_state, ast, ast_node),
         }
     }
 
-    fn compile_zero_or_more(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
-        let node = ast.nodes.get(ast_node).unwrap();
+    fn compile_group(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
+        if let SyntaxType::Group(group) | SyntaxType::NamedGroup(group, _) = ast.nodes.get(ast_node).unwrap().node_type {
+            let node = ast.nodes.get(ast_node).unwrap();
 
-        self.states.nodes.push(StateNode{ state_type: StateType::None, next: vec![] });
-        let state = self.states.nodes.len() - 1;
+            self.states.nodes.push(StateNode{ state_type: StateType::Save(group * 2), next: vec![] });
+            let state = self.states.nodes.len() - 1;
 
-        let prev_state = self.states.nodes.get_mut(prev_state).unwrap();
-        prev_state.next.push(state);
+            let prev_state = self.states.nodes.get_mut(prev_state).unwrap();
+            prev_state.next.push(state);
 
-        let next_state = self.compile_next(state, ast, node.children[0]);
-        let next_state = self.states.nodes.get_mut(next_state).unwrap();
-        next_state.next.push(state);
+            let next_state = self.compile_next(state, ast, node.children[0]);
 
-        state
+            self.states.nodes.push(StateNode{ state_type: StateType::Save(group * 2 + 1), next: vec![] });
+            let state = self.states.nodes.len() - 1;
+
+            let next_state = self.states.nodes.get_mut(next_state).unwrap();
+            next_state.next.push(state);
+
+            state
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// A new state, used as a junction between other states
+    fn push_none(&mut self) -> usize {
+        self.states.nodes.push(StateNode{ state_type: StateType::None, next: vec![] });
+        self.states.nodes.len() - 1
+    }
+
+    /// Adds a transition, transitions added first have a higher priority
+    fn push_next(&mut self, state: usize, next_state: usize) {
+        self.states.nodes.get_mut(state).unwrap().next.push(next_state);
     }
 
-    fn compile_optional(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
+    fn compile_zero_or_more(&mut self, prev_state: usize, ast: &AST, ast_node: usize, greedy: bool) -> usize {
         let node = ast.nodes.get(ast_node).unwrap();
-        let next_state = self.compile_next(prev_state, ast, node.children[0]);
 
-        self.states.nodes.push(StateNode{ state_type: StateType::None, next: vec![] });
-        let state = self.states.nodes.len() - 1;
-        
-        let prev_state = self.states.nodes.get_mut(prev_state).unwrap();
-        prev_state.next.push(state);
+        let state = self.push_none();
+        self.push_next(prev_state, state);
 
-        let next_state = self.states.nodes.get_mut(next_state).unwrap();
-        next_state.next.push(state);
+        if greedy {
+            let next_state = self.compile_next(state, ast, node.children[0]);
+            self.push_next(next_state, state);
 
-        state
+            state
+        } else {
+            let exit_state = self.push_none();
+            self.push_next(state, exit_state);
+
+            let next_state = self.compile_next(state, ast, node.children[0]);
+            self.push_next(next_state, state);
+
+            exit_state
+        }
     }
 
-    fn compile_one_or_more(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
+    fn compile_optional(&mut self, prev_state: usize, ast: &AST, ast_node: usize, greedy: bool) -> usize {
         let node = ast.nodes.get(ast_node).unwrap();
 
-        self.states.nodes.push(StateNode{ state_type: StateType::None, next: vec![] });
-        let state = self.states.nodes.len() - 1;
+        if greedy {
+            let next_state = self.compile_next(prev_state, ast, node.children[0]);
 
-        let prev_state = self.states.nodes.get_mut(prev_state).unwrap();
-        prev_state.next.push(state);
+            let state = self.push_none();
+            self.push_next(prev_state, state);
+            self.push_next(next_state, state);
+
+            state
+        } else {
+            let state = self.push_none();
+            self.push_next(prev_state, state);
+
+            let next_state = self.compile_next(prev_state, ast, node.children[0]);
+            self.push_next(next_state, state);
+
+            state
+        }
+    }
+
+    fn compile_one_or_more(&mut self, prev_state: usize, ast: &AST, ast_node: usize, greedy: bool) -> usize {
+        let node = ast.nodes.get(ast_node).unwrap();
+
+        let state = self.push_none();
+        self.push_next(prev_state, state);
 
         let next_state = self.compile_next(state, ast, node.children[0]);
-        let next_state_node = self.states.nodes.get_mut(next_state).unwrap();
-        next_state_node.next.push(state);
- 
-        next_state
+
+        if greedy {
+            self.push_next(next_state, state);
+
+            next_state
+        } else {
+            let exit_state = self.push_none();
+            self.push_next(next_state, exit_state);
+            self.push_next(next_state, state);
+
+            exit_state
+        }
     }
 
     fn compile_once(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
         let node = ast.nodes.get(ast_node).unwrap();
+
+        if node.children.is_empty() {
+            // the empty regex still needs its own state, so alternatives keep their order
+            let state = self.push_none();
+            self.push_next(prev_state, state);
+            return state
+        }
         
         let mut next_state = prev_state;
         
@@ -326,83 +1142,108 @@ impl Regex {
         state
     }
 
-    fn compile_from(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
-        if let SyntaxType::From(from)= ast.nodes.get(ast_node).unwrap().node_type {
-            if from == 0 {
-                self.compile_one_or_more(prev_state, ast, ast_node)
-            } else {
-                let node = ast.nodes.get(ast_node).unwrap();
+    fn compile_exactly(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
+        if let SyntaxType::Exactly(count) = ast.nodes.get(ast_node).unwrap().node_type {
+            let node = ast.nodes.get(ast_node).unwrap();
 
-                let mut next_state = prev_state;
+            let state = self.push_none();
+            self.push_next(prev_state, state);
 
-                for _ in 0..from {
-                    next_state = self.compile_next(next_state, ast, node.children[0]);
-                }
-
-                self.compile_zero_or_more(next_state, ast, ast_node)
+            let mut next_state = state;
+            for _ in 0..count {
+                next_state = self.compile_next(next_state, ast, node.children[0]);
             }
+
+            next_state
         } else {
             unreachable!()
         }
     }
 
-    fn compile_to(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
-        if let SyntaxType::To(to)= ast.nodes.get(ast_node).unwrap().node_type {
+    fn compile_from(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
+        if let SyntaxType::From(from) | SyntaxType::LazyFrom(from) = ast.nodes.get(ast_node).unwrap().node_type {
+            let greedy = matches!(ast.nodes.get(ast_node).unwrap().node_type, SyntaxType::From(_));
             let node = ast.nodes.get(ast_node).unwrap();
-                self.states.nodes.push(StateNode{ state_type: StateType::None, next: vec![] });
-                let state = self.states.nodes.len() - 1;
 
-                let mut next_state = prev_state;
-                let next_state_node = self.states.nodes.get_mut(next_state).unwrap();
-                next_state_node.next.push(state);
+            let mut next_state = prev_state;
 
-                for _ in 0..to {
-                    next_state = self.compile_next(next_state, ast, node.children[0]);
+            for _ in 0..from {
+                next_state = self.compile_next(next_state, ast, node.children[0]);
+            }
 
-                    let next_state_node = self.states.nodes.get_mut(next_state).unwrap();
-                    next_state_node.next.push(state);
-                }
-                state
+            self.compile_zero_or_more(next_state, ast, ast_node, greedy)
+        } else {
+            unreachable!()
+        }
+    }
+
+    fn compile_to(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
+        if let SyntaxType::To(to) | SyntaxType::LazyTo(to) = ast.nodes.get(ast_node).unwrap().node_type {
+            let greedy = matches!(ast.nodes.get(ast_node).unwrap().node_type, SyntaxType::To(_));
+            self.compile_up_to(prev_state, ast, ast_node, to, greedy)
         } else {
             unreachable!()
         }
     }
 
     fn compile_between(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
-        if let SyntaxType::Between(from, to)= ast.nodes.get(ast_node).unwrap().node_type {
+        if let SyntaxType::Between(from, to) | SyntaxType::LazyBetween(from, to) = ast.nodes.get(ast_node).unwrap().node_type {
+            let greedy = matches!(ast.nodes.get(ast_node).unwrap().node_type, SyntaxType::Between(_, _));
             let node = ast.nodes.get(ast_node).unwrap();
 
-                let mut next_state = prev_state;
-                
-                for _ in 0..from {
-                    next_state = self.compile_next(next_state, ast, node.children[0]);
-                }
-
-                self.states.nodes.push(StateNode{ state_type: StateType::None, next: vec![] });
-                let state = self.states.nodes.len() - 1;
-                let next_state_node = self.states.nodes.get_mut(next_state).unwrap();
-                next_state_node.next.push(state);
-
-                for _ in from..to {
-                    next_state = self.compile_next(next_state, ast, node.children[0]);
+            let mut next_state = prev_state;
 
-                    let next_state_node = self.states.nodes.get_mut(next_state).unwrap();
-                    next_state_node.next.push(state);
-                }
+            for _ in 0..from {
+                next_state = self.compile_next(next_state, ast, node.children[0]);
+            }
 
-                state
+            self.compile_up_to(next_state, ast, ast_node, to - from, greedy)
         } else {
             unreachable!()
         }
     }
 
+    /// Compiles between zero and count copies of the node's child, each copy may exit to the returned state
+    fn compile_up_to(&mut self, prev_state: usize, ast: &AST, ast_node: usize, count: usize, greedy: bool) -> usize {
+        let node = ast.nodes.get(ast_node).unwrap();
+
+        let state = self.push_none();
+        let mut next_state = prev_state;
+
+        for _ in 0..count {
+            if !greedy {
+                self.push_next(next_state, state);
+            }
+
+            let prev_state = next_state;
+            next_state = self.compile_next(next_state, ast, node.children[0]);
+
+            if greedy {
+                self.push_next(prev_state, state);
+            }
+        }
+        self.push_next(next_state, state);
+
+        state
+    }
+
     fn compile_atomic(&mut self, prev_state: usize, ast: &AST, ast_node: usize) -> usize {
         let node = ast.nodes.get(ast_node).unwrap();
+        let flags = node.flags;
         let state_type = match &node.node_type {
-            SyntaxType::Symbol(c) => StateType::Symbol(c.clone()),
-            SyntaxType::Set(set) => StateType::Set(set.clone()),
-            SyntaxType::NotSet(set) => StateType::NotSet(set.clone()),
-            SyntaxType::Any => StateType::Any,
+            SyntaxType::Symbol(c) => if flags.case_insensitive && case_variants(*c).len() > 1 {
+                StateType::Set(CharSet::from_symbols(&[SetSymbol::Char(*c)], true))
+            } else {
+                StateType::Symbol(*c)
+            },
+            SyntaxType::Set(set) => StateType::Set(CharSet::from_symbols(set, flags.case_insensitive)),
+            SyntaxType::NotSet(set) => StateType::Set(CharSet::from_symbols(set, flags.case_insensitive).complement()),
+            SyntaxType::Any => if flags.dot_matches_new_line {
+                StateType::Any
+            } else {
+                StateType::AnyExceptNewLine
+            },
+            SyntaxType::Assertion(assertion) => StateType::Assertion(*assertion),
             _ => unreachable!(),
         };
         self.states.nodes.push(StateNode{ state_type, next: vec![] });
@@ -415,13 +1256,14 @@ impl Regex {
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use std::io::prelude::*;
     use std::fs::File;
+    use std::ops::Range;
+    use std::borrow::Cow;
 
-    use super::Regex;
+    use super::{Regex, RegexBuilder, Match, Captures, SplitItem, Input, MatchKind};
 
     #[test]
     fn output_diagram() {
@@ -445,17 +1287,712 @@ mod tests {
         let found = regex.matches("aaaab ab ac aaacab");
 
         assert_eq!(found.len(), 5);
-        assert_eq!(found[0], (0, 5));
-        assert_eq!(found[1], (6, 2));
-        assert_eq!(found[2], (9, 2));
-        assert_eq!(found[3], (12, 4));
-        assert_eq!(found[4], (16, 2));
+        assert_eq!(found[0].range(), 0..5);
+        assert_eq!(found[1].range(), 6..8);
+        assert_eq!(found[2].range(), 9..11);
+        assert_eq!(found[3].range(), 12..16);
+        assert_eq!(found[4].range(), 16..18);
     }
 
     #[test]
     fn test_is_match() {
         let regex = Regex::compile("a+(b|c)").unwrap();
 
-        assert!(matches!(regex.is_match("yas ao cbhj bqwo aaab nme ab"), Some((17, 4))))
+        assert_eq!(regex.is_match("yas ao cbhj bqwo aaab nme ab").map(|m| m.range()), Some(17..21));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_match() {
+        let regex = Regex::compile("β+").unwrap();
+        let string = "αββγ";
+
+        let found = regex.is_match(string).unwrap();
+        assert_eq!((found.start(), found.end()), (2, 6));
+        assert_eq!(found.range(), 2..6);
+        assert_eq!(found.as_str(), "ββ");
+        assert_eq!(&string[found.range()], "ββ");
+        assert_eq!(found.len(), 4);
+        assert!(!found.is_empty());
+
+        let regex = Regex::compile("([^ ]+) ([^ ]+)").unwrap();
+        let captures = regex.captures("über straße").unwrap();
+        assert_eq!(captures.get(1).map(|m| m.as_str()), Some("über"));
+        assert_eq!(captures.get(2).map(|m| m.as_str()), Some("straße"));
+        assert_eq!(regex.matches("é").len(), 0);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let regex = Regex::compile("a+(b|c)").unwrap();
+        let mut found = regex.find_iter("aab xac ab");
+        assert_eq!(found.next().map(|m| m.range()), Some(0..3));
+        assert_eq!(found.next().map(|m| m.as_str()), Some("ac"));
+        assert_eq!(found.next().map(|m| m.range()), Some(8..10));
+        assert_eq!(found.next(), None);
+        assert_eq!(found.next(), None);
+
+        let mut captures = regex.captures_iter("aab xac ab");
+        assert_eq!(captures.next().and_then(|captures| captures.get(1)).map(|m| m.as_str()), Some("b"));
+        assert_eq!(captures.next().and_then(|captures| captures.get(1)).map(|m| m.as_str()), Some("c"));
+
+        let regex = Regex::compile("\\d+").unwrap();
+        let string = "1 22 333 ".repeat(1000);
+        assert_eq!(regex.find_iter(&string).take(2).map(|m| m.as_str()).collect::<Vec<_>>(), vec!["1", "22"]);
+        assert_eq!(regex.find_iter(&string).count(), 3000);
+        assert_eq!(regex.find_iter(&string).collect::<Vec<_>>(), regex.matches(&string));
+
+        let regex = Regex::compile("x*").unwrap();
+        assert_eq!(ranges(regex.find_iter("axx").collect()), vec![0..0, 1..3, 3..3]);
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::compile("(\\d+)-(?<month>\\d+)").unwrap();
+        assert_eq!(regex.replace("on 2022-08 and 2023-01", "$2/$1"), "on 08/2022 and 2023-01");
+        assert_eq!(regex.replace_all("on 2022-08 and 2023-01", "${month}/${1}"), "on 08/2022 and 01/2023");
+        assert_eq!(regex.replacen("1-2 3-4 5-6", 2, "[$0]"), "[1-2] [3-4] 5-6");
+        assert_eq!(regex.replacen("1-2 3-4 5-6", 0, "[$0]"), "[1-2] [3-4] [5-6]");
+        assert_eq!(regex.replace_all("1-2", "$$1 $3 ${year} $ $x"), "$1   $ $x");
+        assert_eq!(regex.replace_all("1-2", "${1}0 $10"), "10 ");
+        assert!(matches!(regex.replace_all("no dates", "$1"), Cow::Borrowed("no dates")));
+
+        let regex = Regex::compile("[a-z]+").unwrap();
+        assert_eq!(regex.replace_all("ab cd", |captures: &Captures| captures.get(0).unwrap().as_str().to_uppercase()), "AB CD");
+        let mut count = 0;
+        assert_eq!(regex.replace_all("ab cd", |_: &Captures| { count += 1; count.to_string() }), "1 2");
+
+        let regex = Regex::compile("x*").unwrap();
+        assert_eq!(regex.replace_all("abxx", "-"), "-a-b--");
+        assert_eq!(regex.replace_all("αβ", "-"), "-α-β-");
+
+        let regex = Regex::compile("\\d").unwrap();
+        assert_eq!(regex.replace_all(Input::new("1 2 3").span(1..4), "x"), "1 x 3");
+        assert!(matches!(regex.replace_all(Input::new("1 a").start(1), "x"), Cow::Borrowed("1 a")));
+    }
+
+    #[test]
+    fn test_split() {
+        let regex = Regex::compile(" *, *").unwrap();
+        assert_eq!(regex.split("a, b ,c,,d").collect::<Vec<_>>(), vec!["a", "b", "c", "", "d"]);
+        assert_eq!(regex.split(",a,").collect::<Vec<_>>(), vec!["", "a", ""]);
+        assert_eq!(regex.split("abc").collect::<Vec<_>>(), vec!["abc"]);
+        assert_eq!(regex.split("").collect::<Vec<_>>(), vec![""]);
+
+        assert_eq!(regex.splitn("a, b ,c,,d", 3).collect::<Vec<_>>(), vec!["a", "b", "c,,d"]);
+        assert_eq!(regex.splitn("a, b", 5).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(regex.splitn("a, b", 1).collect::<Vec<_>>(), vec!["a, b"]);
+        assert_eq!(regex.splitn("a, b", 0).count(), 0);
+
+        let regex = Regex::compile("\\s+").unwrap();
+        let string = "x  y\tz ".repeat(1000);
+        assert_eq!(regex.split(&string).take(3).collect::<Vec<_>>(), vec!["x", "y", "z"]);
+        assert_eq!(regex.split(&string).count(), 3001);
+
+        let regex = Regex::compile("").unwrap();
+        assert_eq!(regex.split("αb").collect::<Vec<_>>(), vec!["", "α", "b", ""]);
+
+        let regex = Regex::compile("([+-])").unwrap();
+        let items = regex.split_with_delimiters("1+2-3").map(|item| match item {
+            SplitItem::Field(field) => field.to_string(),
+            SplitItem::Delimiter(captures) => format!("<{}>", captures.get(1).unwrap().as_str()),
+        }).collect::<Vec<_>>();
+        assert_eq!(items, vec!["1", "<+>", "2", "<->", "3"]);
+        assert_eq!(regex.split_with_delimiters("12").collect::<Vec<_>>(), vec![SplitItem::Field("12")]);
+        assert_eq!(regex.split_with_delimiters("+").count(), 3);
+        assert_eq!(regex.split_with_delimiters(Input::new("1+2-3").span(2..5)).count(), 3);
+
+        let regex = Regex::compile(",").unwrap();
+        assert_eq!(regex.split(Input::new("a,b,c,d").span(2..5)).collect::<Vec<_>>(), vec!["b", "c"]);
+        assert_eq!(regex.splitn(Input::new("a,b,c,d").start(2), 2).collect::<Vec<_>>(), vec!["b", "c,d"]);
+    }
+
+    #[test]
+    fn test_input() {
+        let regex = Regex::compile("\\d+").unwrap();
+        assert_eq!(regex.find_at("12 345", 1).map(|m| m.range()), Some(1..2));
+        assert_eq!(regex.find_at("12 345", 2).map(|m| m.as_str()), Some("345"));
+        assert_eq!(regex.find_at("12 345", 6), None);
+        assert!(regex.has_match_at("12 345", 3) && !regex.has_match_at("12 a", 2));
+        assert_eq!(regex.is_match_at("12 345", 3).map(|m| m.range()), Some(3..6));
+        assert_eq!(regex.is_match_at("12 a", 2), None);
+
+        let regex = Regex::compile("\\bb").unwrap();
+        assert_eq!(regex.find_at("ab b", 1).map(|m| m.range()), Some(3..4));
+        let regex = Regex::compile("^b|\\Ab").unwrap();
+        assert_eq!(regex.find_at("ab", 1), None);
+        let regex = Regex::compile("a$").unwrap();
+        assert_eq!(regex.is_match(Input::new("aab").span(0..2)), None);
+        assert_eq!(regex.is_match(Input::new("ab").span(0..1)), None);
+        let regex = Regex::compile("a\\Z").unwrap();
+        assert_eq!(regex.is_match(Input::new("aa\n").span(0..2)).map(|m| m.range()), Some(1..2));
+        assert_eq!(regex.is_match(Input::new("aa\nb").span(0..2)), None);
+
+        let regex = Regex::compile("[a-z]+").unwrap();
+        assert_eq!(ranges(regex.matches(Input::new("ab cd ef").span(1..7))), vec![1..2, 3..5, 6..7]);
+        assert_eq!(regex.is_match(Input::new("αβ ab").start(2)).map(|m| m.range()), Some(5..7));
+        assert!(regex.test(Input::new("12ab34").span(2..4)));
+        assert!(!regex.test(Input::new("12ab34").span(1..4)));
+
+        let regex = Regex::compile("[a-z]+|\\s+").unwrap();
+        assert_eq!(regex.is_match(Input::new("12 ab").anchored(true)), None);
+        assert_eq!(regex.is_match(Input::new("12 ab").start(2).anchored(true)).map(|m| m.range()), Some(2..3));
+        assert_eq!(ranges(regex.matches(Input::new("ab cd").anchored(true))), vec![0..2, 2..3, 3..5]);
+        assert_eq!(ranges(regex.matches(Input::new("ab 1 cd").anchored(true))), vec![0..2, 2..3]);
+        let regex = Regex::compile("a*").unwrap();
+        assert_eq!(ranges(regex.matches(Input::new("abb").anchored(true))), vec![0..1, 1..1]);
+        assert_eq!(ranges(regex.matches(Input::new("").anchored(true))), vec![0..0]);
+
+        let regex = Regex::compile("a+|ab").unwrap();
+        assert_eq!(regex.is_match("aaab").map(|m| m.range()), Some(0..3));
+        assert_eq!(regex.is_match(Input::new("aaab").earliest(true)).map(|m| m.range()), Some(0..1));
+        assert_eq!(ranges(regex.matches(Input::new("aaab").earliest(true))), vec![0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_input_span() {
+        Input::new("αβ").span(1..2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_find_at_out_of_bounds() {
+        Regex::compile("a").unwrap().find_at("a", 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_is_match_at_char_boundary() {
+        Regex::compile("a").unwrap().is_match_at("αa", 1);
+    }
+
+    #[test]
+    fn test_match_kind() {
+        let longest = |code: &str| RegexBuilder::new(code).match_kind(MatchKind::LeftmostLongest).build().unwrap();
+
+        assert_eq!(ranges(Regex::compile("a|ab").unwrap().matches("ab")), vec![0..1]);
+        assert_eq!(ranges(longest("a|ab").matches("ab")), vec![0..2]);
+
+        assert_eq!(ranges(Regex::compile("a+?").unwrap().matches("aaa")), vec![0..1, 1..2, 2..3]);
+        assert_eq!(ranges(longest("a+?").matches("aaa")), vec![0..3]);
+
+        assert_eq!(ranges(Regex::compile("(a|ab)(c|bcd)").unwrap().matches("abcd")), vec![0..4]);
+        assert_eq!(ranges(Regex::compile("a*?|b").unwrap().matches("b")), vec![0..0, 1..1]);
+        assert_eq!(ranges(longest("a*?|b").matches("b")), vec![0..1, 1..1]);
+
+        let regex = longest("(a|ab)(b?)");
+        let captures = regex.captures("ab").unwrap();
+        assert_eq!(captures.get(0).map(|m| m.range()), Some(0..2));
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(0..1));
+
+        assert!(longest("a|ab").test("ab"));
+        assert_eq!(longest("a|ab").is_match(Input::new("ab").earliest(true)).map(|m| m.range()), Some(0..1));
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let regex = Regex::compile("aa").unwrap();
+        assert_eq!(ranges(regex.find_overlapping_iter("aaaa").collect()), vec![0..2, 1..3, 2..4]);
+
+        let regex = Regex::compile("a+").unwrap();
+        assert_eq!(ranges(regex.find_overlapping_iter("aab").collect()), vec![0..1, 0..2]);
+        assert_eq!(ranges(regex.find_overlapping_iter(Input::new("aab").start(1)).collect()), vec![1..2]);
+        assert_eq!(ranges(regex.find_overlapping_iter(Input::new("baab").anchored(true)).collect()), vec![]);
+        assert_eq!(ranges(regex.find_overlapping_iter(Input::new("aab").earliest(true)).collect()), vec![0..1]);
+        assert_eq!(regex.find_overlapping_iter("bbb").count(), 0);
+
+        let regex = Regex::compile("he|hers|she|s").unwrap();
+        let found = regex.find_overlapping_iter("ushers").map(|m| m.as_str()).collect::<Vec<_>>();
+        assert_eq!(found, vec!["s", "she", "hers"]);
+
+        let regex = Regex::compile("b+c|a+b").unwrap();
+        assert_eq!(ranges(regex.find_overlapping_iter("aabbc").collect()), vec![0..3, 2..5]);
+
+        let regex = Regex::compile("a*").unwrap();
+        assert_eq!(ranges(regex.find_overlapping_iter("αa").collect()), vec![0..0, 2..2, 2..3]);
+
+        let regex = Regex::compile("\\w+@\\w+|\\w+").unwrap();
+        assert_eq!(regex.shortest_match("ab@cd"), Some(1));
+        assert_eq!(regex.is_match("ab@cd").map(|m| m.end()), Some(5));
+        assert_eq!(regex.shortest_match(Input::new("ab@cd").start(2)), Some(4));
+        assert_eq!(regex.shortest_match("@"), None);
+    }
+
+    #[test]
+    fn test_single_pass() {
+        let regex = Regex::compile("abcd|bc").unwrap();
+        assert_eq!(ranges(regex.matches("abcd abce")), vec![0..4, 6..8]);
+        let regex = RegexBuilder::new("a|bcd").match_kind(MatchKind::LeftmostLongest).build().unwrap();
+        assert_eq!(ranges(regex.matches("abcd")), vec![0..1, 1..4]);
+        let regex = RegexBuilder::new("ab|bcde|b").match_kind(MatchKind::LeftmostLongest).build().unwrap();
+        assert_eq!(ranges(regex.matches("abcde")), vec![0..2]);
+        assert_eq!(ranges(regex.matches("xbcde")), vec![1..5]);
+
+        let regex = Regex::compile("(a)x|b").unwrap();
+        let captures = regex.captures_iter("axb").map(|captures| captures.get(1).map(|m| m.range())).collect::<Vec<_>>();
+        assert_eq!(captures, vec![Some(0..1), None]);
+
+        let regex = Regex::compile("x+y").unwrap();
+        let string = format!("{}y", "x".repeat(20000));
+        assert_eq!(regex.is_match(&string).map(|m| m.range()), Some(0..20001));
+        let string = "x".repeat(20000);
+        assert_eq!(regex.is_match(&string), None);
+        assert_eq!(regex.matches(&string).len(), 0);
+
+        let regex = Regex::compile("[a-z]+\\d").unwrap();
+        let string = "ab ".repeat(10000) + "ab1";
+        assert_eq!(regex.is_match(&string).map(|m| m.start()), Some(30000));
+    }
+
+    #[test]
+    fn test_overlapping_single_pass() {
+        let regex = Regex::compile("x+y|x").unwrap();
+        let string = format!("{}y", "x".repeat(20000));
+        let found = ranges(regex.find_overlapping_iter(&string).collect());
+        assert_eq!(found.len(), 20001);
+        assert_eq!(found[..3], [0..1, 0..20001, 1..2]);
+        assert_eq!(found.last(), Some(&(19999..20000)));
+
+        let regex = Regex::compile("[a-z]+\\d").unwrap();
+        let string = "ab ".repeat(10000) + "ab1";
+        assert_eq!(ranges(regex.find_overlapping_iter(&string).collect()), vec![30000..30003]);
+    }
+
+    #[test]
+    fn test_escapes() {
+        let regex = Regex::compile("\\(a\\*\\)\\.[\\[\\]]\\x41\\u{3b1}").unwrap();
+
+        assert!(regex.test("(a*).[Aα"));
+        assert!(regex.test("(a*).]Aα"));
+        assert!(!regex.test("(aa).]Aα"));
+        assert!(Regex::compile("a\\q").is_err());
+    }
+
+    #[test]
+    fn test_classes() {
+        let regex = Regex::compile("\\d+-\\w+\\s\\S[\\d_-]+").unwrap();
+
+        assert!(regex.test("2022-snake_case -_0-9"));
+        assert!(!regex.test("2022-snake case -_0-9"));
+        assert!(!regex.test("a-b c d"));
+
+        let regex = Regex::compile("[^\\D]\\D\\W[\\W\\d]").unwrap();
+        assert!(regex.test("1a 2"));
+        assert!(regex.test("1a !"));
+        assert!(!regex.test("1a a"));
+
+        assert_eq!(ranges(Regex::compile("\\d+").unwrap().matches("ab 12 345")), vec![3..5, 6..9]);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let regex = Regex::compile("^ab").unwrap();
+        assert_eq!(ranges(regex.matches("abab\nab")), vec![0..2]);
+
+        let regex = Regex::compile("ab$").unwrap();
+        assert_eq!(ranges(regex.matches("abab\nab")), vec![5..7]);
+        assert_eq!(regex.is_match("ab\n"), None);
+
+        let regex = Regex::compile("\\Aa|b\\z").unwrap();
+        assert_eq!(ranges(regex.matches("abab")), vec![0..1, 3..4]);
+
+        let regex = Regex::compile("b\\Z").unwrap();
+        assert_eq!(ranges(regex.matches("bb\n")), vec![1..2]);
+        assert_eq!(ranges(regex.matches("bb")), vec![1..2]);
+
+        let regex = RegexBuilder::new("^ab$").multi_line(true).build().unwrap();
+        assert_eq!(ranges(regex.matches("ab\nabc\nab")), vec![0..2, 7..9]);
+
+        let regex = RegexBuilder::new("\\Aab\\z").multi_line(true).build().unwrap();
+        assert_eq!(regex.matches("ab\nab"), vec![]);
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        let regex = Regex::compile("\\bcat\\b").unwrap();
+        assert_eq!(ranges(regex.matches("cat concat cats cat_ (cat)")), vec![0..3, 22..25]);
+
+        let regex = Regex::compile("\\Bcat").unwrap();
+        assert_eq!(ranges(regex.matches("cat concat cats")), vec![7..10]);
+
+        let regex = Regex::compile("\\bcafé\\b").unwrap();
+        assert_eq!(ranges(regex.matches("café cafés")), vec![6..11]);
+
+        let regex = RegexBuilder::new("\\bcafé\\b").unicode(true).build().unwrap();
+        assert_eq!(ranges(regex.matches("café cafés")), vec![0..5]);
+
+        let regex = RegexBuilder::new("\\bnaïve\\b").unicode(true).build().unwrap();
+        assert_eq!(ranges(regex.matches("naïve")), vec![0..6]);
+    }
+
+    #[test]
+    fn test_captures() {
+        let regex = Regex::compile("(\\d+)-(\\d+)(-(\\d+))?").unwrap();
+        assert_eq!(regex.captures_len(), 5);
+
+        let captures = regex.captures("on 2022-08 and 2022-08-21").unwrap();
+        assert_eq!(captures.iter().map(|m| m.map(|m| m.range())).collect::<Vec<_>>(), vec![Some(3..10), Some(3..7), Some(8..10), None, None]);
+
+        let captures = regex.captures_iter("on 2022-08 and 2022-08-21").collect::<Vec<_>>();
+        assert_eq!(captures.len(), 2);
+        assert_eq!(captures[1].iter().map(|m| m.map(|m| m.range())).collect::<Vec<_>>(), vec![Some(15..25), Some(15..19), Some(20..22), Some(22..25), Some(23..25)]);
+
+        let regex = Regex::compile("(a|b)+").unwrap();
+        let captures = regex.captures("abab").unwrap();
+        assert_eq!(captures.get(0).map(|m| m.range()), Some(0..4));
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(3..4));
+        assert_eq!(captures.get(2), None);
+
+        let regex = Regex::compile("((a)(b))c").unwrap();
+        let captures = regex.captures("xabc").unwrap();
+        assert_eq!(captures.iter().map(|m| m.map(|m| m.range())).collect::<Vec<_>>(), vec![Some(1..4), Some(1..3), Some(1..2), Some(2..3)]);
+
+        assert!(Regex::compile("a").unwrap().captures("b").is_none());
+    }
+
+    #[test]
+    fn test_named_captures() {
+        let regex = Regex::compile("(?<level>[A-Z]+): (?P<message>.+) \\((\\d+)\\)").unwrap();
+        assert_eq!(regex.capture_names().collect::<Vec<_>>(), vec![None, Some("level"), Some("message"), None]);
+        assert_eq!(regex.capture_index("message"), Some(2));
+        assert_eq!(regex.capture_index("code"), None);
+
+        let captures = regex.captures("ERROR: disk full (28)").unwrap();
+        assert_eq!(captures.name("level").map(|m| m.range()), Some(0..5));
+        assert_eq!(captures.name("message").map(|m| m.range()), Some(7..16));
+        assert_eq!(captures.get(3).map(|m| m.range()), Some(18..20));
+        assert_eq!(captures.name("code"), None);
+
+        assert!(Regex::compile("(?<a>x)(?<a>y)").is_err());
+    }
+
+    #[test]
+    fn test_flag_groups() {
+        let regex = Regex::compile("(?:a|b)+(c)").unwrap();
+        assert_eq!(regex.captures_len(), 2);
+        assert_eq!(regex.captures("abbc").unwrap().get(1).map(|m| m.range()), Some(3..4));
+
+        let regex = Regex::compile("a(?i)bc|d").unwrap();
+        assert_eq!(ranges(regex.matches("aBC abc ABC D")), vec![0..3, 4..7, 12..13]);
+
+        let regex = Regex::compile("(?i:a)b(?-i:c)").unwrap();
+        assert_eq!(ranges(regex.matches("Abc ABc AbC abc")), vec![0..3, 12..15]);
+
+        let regex = Regex::compile("(?i)a(?-i:b)").unwrap();
+        assert_eq!(ranges(regex.matches("Ab AB ab")), vec![0..2, 6..8]);
+
+        let regex = Regex::compile("a.c").unwrap();
+        assert_eq!(ranges(regex.matches("abc a\nc")), vec![0..3]);
+        let regex = Regex::compile("(?s)a.c").unwrap();
+        assert_eq!(ranges(regex.matches("abc a\nc")), vec![0..3, 4..7]);
+        let regex = RegexBuilder::new("a.c").dot_matches_new_line(true).build().unwrap();
+        assert_eq!(ranges(regex.matches("abc a\nc")), vec![0..3, 4..7]);
+
+        let regex = Regex::compile("(?m)^a$").unwrap();
+        assert_eq!(ranges(regex.matches("a\na")), vec![0..1, 2..3]);
+
+        let regex = Regex::compile("(?x) a b # match ab\n | c\\ d").unwrap();
+        assert_eq!(ranges(regex.matches("ab c d")), vec![0..2, 3..6]);
+        let regex = Regex::compile("(?x: a b ) c").unwrap();
+        assert_eq!(ranges(regex.matches("ab c")), vec![0..4]);
+
+        assert!(Regex::compile("(?q)a").is_err());
+        assert!(Regex::compile("(?=a)").is_err());
+    }
+
+    #[test]
+    fn test_sets() {
+        let regex = Regex::compile("[à-ÿ]+").unwrap();
+        assert_eq!(ranges(regex.matches("déjà vu")), vec![1..3, 4..6]);
+        let regex = Regex::compile("[α-ω]+").unwrap();
+        assert_eq!(ranges(regex.matches("abc αβγ")), vec![4..10]);
+        let regex = Regex::compile("[\\x00-\\x1f]").unwrap();
+        assert_eq!(ranges(regex.matches("a\tb\n")), vec![1..2, 3..4]);
+        let regex = Regex::compile("[!-/]+").unwrap();
+        assert_eq!(ranges(regex.matches("a+b*(c)")), vec![1..2, 3..5, 6..7]);
+
+        let regex = Regex::compile("[-a]+").unwrap();
+        assert_eq!(ranges(regex.matches("b-a-b")), vec![1..4]);
+        let regex = Regex::compile("[a-]+").unwrap();
+        assert_eq!(ranges(regex.matches("b-a-b")), vec![1..4]);
+        let regex = Regex::compile("[^-a]+").unwrap();
+        assert_eq!(ranges(regex.matches("b-a-b")), vec![0..1, 4..5]);
+        let regex = Regex::compile("[a-c-e]+").unwrap();
+        assert_eq!(ranges(regex.matches("b-ed")), vec![0..3]);
+
+        assert!(Regex::compile("[z-a]").is_err());
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let regex = Regex::compile("[a-z&&[^aeiou]]+").unwrap();
+        assert_eq!(ranges(regex.matches("strength is")), vec![0..3, 4..8, 10..11]);
+        let regex = Regex::compile("[\\p{L}--\\p{Latin}]+").unwrap();
+        assert_eq!(ranges(regex.matches("abc αβγ мир")), vec![4..10, 11..17]);
+        let regex = Regex::compile("[a-f~~d-z]+").unwrap();
+        assert_eq!(ranges(regex.matches("abcdefgz")), vec![0..3, 6..8]);
+        let regex = Regex::compile("[\\w&&\\D--_]+").unwrap();
+        assert_eq!(ranges(regex.matches("ab_12cd")), vec![0..2, 5..7]);
+        let regex = Regex::compile("[[a-c][x-z]]+").unwrap();
+        assert_eq!(ranges(regex.matches("abdxy")), vec![0..2, 3..5]);
+        let regex = Regex::compile("[^[a-c][x-z]]+").unwrap();
+        assert_eq!(ranges(regex.matches("abdxy")), vec![2..3]);
+        let regex = Regex::compile("[a-z&&[^[:^alpha:]]--[^x]]").unwrap();
+        assert_eq!(ranges(regex.matches("axyx")), vec![1..2, 3..4]);
+
+        let regex = Regex::compile("(?i)[a-z&&[^aeiou]]+").unwrap();
+        assert_eq!(ranges(regex.matches("STRength Is")), vec![0..3, 4..8, 10..11]);
+
+        assert!(Regex::compile("[a--]").is_err());
+        assert!(Regex::compile("[[z-a]]").is_err());
+    }
+
+    #[test]
+    fn test_posix_classes() {
+        let regex = Regex::compile("[[:alpha:]]+").unwrap();
+        assert_eq!(ranges(regex.matches("ab1 cd")), vec![0..2, 4..6]);
+        let regex = Regex::compile("[[:digit:][:space:]]+").unwrap();
+        assert_eq!(ranges(regex.matches("a1 2b")), vec![1..4]);
+        let regex = Regex::compile("[[:punct:]]+").unwrap();
+        assert_eq!(ranges(regex.matches("a!?b[~]")), vec![1..3, 4..7]);
+        let regex = Regex::compile("[[:^alpha:]]+").unwrap();
+        assert_eq!(ranges(regex.matches("ab1 cd")), vec![2..4]);
+        let regex = Regex::compile("[^[:alnum:]]+").unwrap();
+        assert_eq!(ranges(regex.matches("ab1 _cd")), vec![3..5]);
+        let regex = Regex::compile("[[:upper:][:lower:]]").unwrap();
+        assert!(regex.test("Q") && regex.test("q") && !regex.test("é"));
+        let regex = Regex::compile("(?i)[[:upper:]]").unwrap();
+        assert!(regex.test("q"));
+
+        assert!(Regex::compile("[[:foo:]]").is_err());
+    }
+
+    #[test]
+    fn test_properties() {
+        let regex = Regex::compile("\\p{L}+").unwrap();
+        assert_eq!(ranges(regex.matches("héllo, мир 123 漢字")), vec![0..6, 8..14, 19..25]);
+        let regex = Regex::compile("\\p{Lu}\\p{Ll}+").unwrap();
+        assert_eq!(ranges(regex.matches("Hello WORLD Émile")), vec![0..5, 12..18]);
+        let regex = Regex::compile("\\p{Nd}+").unwrap();
+        assert_eq!(ranges(regex.matches("12 ٣٤ x")), vec![0..2, 3..7]);
+        let regex = Regex::compile("\\p{Greek}+").unwrap();
+        assert_eq!(ranges(regex.matches("alpha αβγ Ω")), vec![6..12, 13..15]);
+        let regex = Regex::compile("\\p{Han}+").unwrap();
+        assert_eq!(ranges(regex.matches("漢字 かな")), vec![0..6]);
+
+        let regex = Regex::compile("\\PL+").unwrap();
+        assert_eq!(ranges(regex.matches("ab, cd")), vec![2..4]);
+        let regex = Regex::compile("[\\p{Greek}\\d]+").unwrap();
+        assert_eq!(ranges(regex.matches("a α1β b")), vec![2..7]);
+        let regex = Regex::compile("[^\\p{L}\\s]+").unwrap();
+        assert_eq!(ranges(regex.matches("ab 12,c")), vec![3..6]);
+
+        let regex = Regex::compile("(?i)\\p{Lu}+").unwrap();
+        assert!(regex.test("aBc"));
+
+        assert!(Regex::compile("\\p{Klingon}").is_err());
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let regex = RegexBuilder::new("error").case_insensitive(true).build().unwrap();
+        assert_eq!(ranges(regex.matches("Error ERROR error eRRoR erro")), vec![0..5, 6..11, 12..17, 18..23]);
+        let regex = Regex::compile("(?i)error").unwrap();
+        assert_eq!(ranges(regex.matches("Error ERROR error eRRoR erro")), vec![0..5, 6..11, 12..17, 18..23]);
+
+        let regex = Regex::compile("(?i)[a-f]+").unwrap();
+        assert_eq!(ranges(regex.matches("CaFe g")), vec![0..4]);
+        let regex = Regex::compile("(?i)[^a-f]+").unwrap();
+        assert_eq!(ranges(regex.matches("CaFe g")), vec![4..6]);
+        let regex = Regex::compile("(?i)[x]").unwrap();
+        assert!(regex.test("X"));
+
+        let regex = Regex::compile("(?i)straße").unwrap();
+        assert!(regex.test("STRAẞE"));
+        let regex = Regex::compile("(?i)σ+").unwrap();
+        assert!(regex.test("Σσς"));
+        let regex = Regex::compile("(?i)k").unwrap();
+        assert!(regex.test("\u{212A}"));
+        let regex = Regex::compile("(?i)[a-z]").unwrap();
+        assert!(regex.test("\u{212A}"));
+        let regex = Regex::compile("(?i)i").unwrap();
+        assert!(!regex.test("ı"));
+
+        let regex = Regex::compile("(?i)a(?-i)b").unwrap();
+        assert!(regex.test("Ab"));
+        assert!(!regex.test("AB"));
+    }
+
+    #[test]
+    fn test_repetitions() {
+        let regex = Regex::compile("a{3}").unwrap();
+        assert_eq!(ranges(regex.matches("aa aaa aaaaaaa")), vec![3..6, 7..10, 10..13]);
+        let regex = Regex::compile("(ab){2}c").unwrap();
+        assert!(regex.test("ababc") && !regex.test("abc") && !regex.test("abababc"));
+        let regex = Regex::compile("x{0}y").unwrap();
+        assert!(regex.test("y") && !regex.test("xy"));
+        let regex = Regex::compile("a{2}?").unwrap();
+        assert_eq!(ranges(regex.matches("aaaaa")), vec![0..2, 2..4]);
+
+        let regex = Regex::compile("a{3").unwrap();
+        assert!(regex.test("a{3"));
+        let regex = Regex::compile("a{x}").unwrap();
+        assert!(regex.test("a{x}"));
+
+        let strict = |string: &str| RegexBuilder::new(string).strict(true).build().map(|_| ()).map_err(|err| err.to_string());
+        assert_eq!(strict("a{3}"), Ok(()));
+        assert_eq!(strict("a\\{3"), Ok(()));
+        assert_eq!(strict("a{3"), Err("missing '}' to close repetition '{3'".to_string()));
+        assert_eq!(strict("a{a,b}"), Err("invalid repetition '{a,b}', expected {n}, {n,}, {,m} or {n,m}".to_string()));
+        assert_eq!(strict("a{5,2}"), Err("from must be lower or equal to to in range".to_string()));
+    }
+
+    #[test]
+    fn test_repetition_bounds() {
+        // every repetition is compared against the same regex written out by hand, "a{2,4}" is "aaa?a?"
+        let expand = |atom: &str, from: usize, to: Option<usize>, lazy: bool| {
+            let lazy = if lazy { "?" } else { "" };
+            let mut expansion = atom.repeat(from);
+            match to {
+                Some(to) => for _ in from..to {
+                    expansion.push_str(&format!("{}?{}", atom, lazy));
+                },
+                None => expansion.push_str(&format!("{}*{}", atom, lazy)),
+            }
+            expansion
+        };
+
+        let mut repetitions = vec![];
+        for from in 0..=3 {
+            repetitions.push((format!("{{{},}}", from), from, None));
+            repetitions.push((format!("{{{}}}", from), from, Some(from)));
+            for to in from..=4 {
+                repetitions.push((format!("{{{},{}}}", from, to), from, Some(to)));
+                if from == 0 {
+                    repetitions.push((format!("{{,{}}}", to), from, Some(to)));
+                }
+            }
+        }
+
+        for (atom, text) in [("a", "a"), ("(?:ab)", "ab")] {
+            for (repetition, from, to) in &repetitions {
+                for lazy in [false, true] {
+                    let quantifier = format!("{}{}", repetition, if lazy { "?" } else { "" });
+                    let regex = Regex::compile(&format!("x{}{}y", atom, quantifier)).unwrap();
+                    let reference = Regex::compile(&format!("x{}y", expand(atom, *from, *to, lazy))).unwrap();
+                    let unanchored = Regex::compile(&format!("x{}{}", atom, quantifier)).unwrap();
+                    let unanchored_reference = Regex::compile(&format!("x{}", expand(atom, *from, *to, lazy))).unwrap();
+
+                    for count in 0..=6 {
+                        let input = format!("x{}y", text.repeat(count));
+                        let expected = count >= *from && to.is_none_or(|to| count <= to);
+                        assert_eq!(regex.test(&input), expected, "x{}{}y on {}", atom, quantifier, input);
+                        assert_eq!(reference.test(&input), expected, "reference for x{}{}y on {}", atom, quantifier, input);
+
+                        let input = format!("x{}", text.repeat(count));
+                        assert_eq!(unanchored.matches(&input), unanchored_reference.matches(&input), "x{}{} on {}", atom, quantifier, input);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let regex = Regex::compile("").unwrap();
+        assert!(regex.test(""));
+        assert!(!regex.test("a"));
+
+        let regex = Regex::compile("x(a|)y").unwrap();
+        assert!(regex.test("xay") && regex.test("xy"));
+        assert!(!regex.test("xby"));
+        let regex = Regex::compile("x(|b)y").unwrap();
+        assert!(regex.test("xby") && regex.test("xy"));
+        let regex = Regex::compile("x()y").unwrap();
+        assert!(regex.test("xy"));
+        let regex = Regex::compile("xa||by").unwrap();
+        assert!(regex.test("xa") && regex.test("by") && !regex.test("xy"));
+        let regex = Regex::compile("x(a||b)y").unwrap();
+        assert!(regex.test("xay") && regex.test("xy") && regex.test("xby"));
+
+        let regex = Regex::compile("x(a|)(a*)").unwrap();
+        let captures = regex.captures("xaa").unwrap();
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(1..2));
+        assert_eq!(captures.get(2).map(|m| m.range()), Some(2..3));
+        let regex = Regex::compile("x(|a)(a*)").unwrap();
+        let captures = regex.captures("xaa").unwrap();
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(1..1));
+        assert_eq!(captures.get(2).map(|m| m.range()), Some(1..3));
+        let regex = Regex::compile("x()y").unwrap();
+        assert_eq!(regex.captures("xy").unwrap().get(1).map(|m| m.range()), Some(1..1));
+    }
+
+    #[test]
+    fn test_empty_matches() {
+        let regex = Regex::compile("a*").unwrap();
+        assert_eq!(ranges(regex.matches("bbb")), vec![0..0, 1..1, 2..2, 3..3]);
+        assert_eq!(ranges(regex.matches("baa")), vec![0..0, 1..3, 3..3]);
+        assert_eq!(ranges(regex.matches("aab")), vec![0..2, 2..2, 3..3]);
+        assert_eq!(ranges(regex.matches("")), vec![0..0]);
+        assert_eq!(regex.is_match("bbb").map(|m| m.range()), Some(0..0));
+        assert!(regex.test("") && regex.test("aaa") && !regex.test("ab"));
+
+        let regex = Regex::compile("").unwrap();
+        assert_eq!(ranges(regex.matches("ab")), vec![0..0, 1..1, 2..2]);
+        assert_eq!(regex.is_match("").map(|m| m.range()), Some(0..0));
+
+        let regex = Regex::compile("\\b").unwrap();
+        assert_eq!(ranges(regex.matches("ab cd")), vec![0..0, 2..2, 3..3, 5..5]);
+        let regex = Regex::compile("$").unwrap();
+        assert_eq!(ranges(regex.matches("ab")), vec![2..2]);
+        let regex = Regex::compile("a+").unwrap();
+        assert_eq!(regex.is_match("bbb"), None);
+        assert!(!regex.test(""));
+
+        let regex = Regex::compile("(a?)b").unwrap();
+        let captures = regex.captures_iter("bab").map(|captures| captures.get(1).map(|m| m.range())).collect::<Vec<_>>();
+        assert_eq!(captures, vec![Some(0..0), Some(1..2)]);
+    }
+
+    #[test]
+    fn test_lazy() {
+        let regex = Regex::compile("<.+?>").unwrap();
+        assert_eq!(ranges(regex.matches("<a><b>")), vec![0..3, 3..6]);
+        let regex = Regex::compile("<.+>").unwrap();
+        assert_eq!(ranges(regex.matches("<a><b>")), vec![0..6]);
+
+        let regex = Regex::compile("a+?").unwrap();
+        assert_eq!(ranges(regex.matches("aaa")), vec![0..1, 1..2, 2..3]);
+        let regex = Regex::compile("ba??").unwrap();
+        assert_eq!(ranges(regex.matches("baa")), vec![0..1]);
+        let regex = Regex::compile("a{2,}?").unwrap();
+        assert_eq!(ranges(regex.matches("aaaaa")), vec![0..2, 2..4]);
+        let regex = Regex::compile("ba{,2}?").unwrap();
+        assert_eq!(ranges(regex.matches("baa")), vec![0..1]);
+        let regex = Regex::compile("a{2,4}?").unwrap();
+        assert_eq!(ranges(regex.matches("aaaaa")), vec![0..2, 2..4]);
+        let regex = Regex::compile("a{2,4}").unwrap();
+        assert_eq!(ranges(regex.matches("aaaaa")), vec![0..4]);
+        let regex = Regex::compile("ba{,2}").unwrap();
+        assert_eq!(ranges(regex.matches("baaa")), vec![0..3]);
+
+        let regex = Regex::compile("(a+?)(a*)").unwrap();
+        let captures = regex.captures("aaaa").unwrap();
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(0..1));
+        assert_eq!(captures.get(2).map(|m| m.range()), Some(1..4));
+
+        let regex = Regex::compile("(a*?)(a+?)b").unwrap();
+        let captures = regex.captures("aaab").unwrap();
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(0..0));
+        assert_eq!(captures.get(2).map(|m| m.range()), Some(0..3));
+
+        let regex = Regex::compile("a.*?c").unwrap();
+        assert!(regex.test("abcbc"));
+        let regex = Regex::compile("a|ab").unwrap();
+        assert!(regex.test("ab"));
+        assert_eq!(ranges(regex.matches("ab")), vec![0..1]);
+    }
+    fn ranges(matches: Vec<Match>) -> Vec<Range<usize>> {
+        matches.iter().map(|m| m.range()).collect()
+    }
+}
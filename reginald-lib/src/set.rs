@@ -0,0 +1,161 @@
+use crate::{lexer::{SetSymbol, SetOperation}, unicode::range_case_variants};
+
+/// A set of characters, stored as sorted inclusive ranges that never overlap or touch
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CharSet {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl CharSet {
+    pub fn new(mut ranges: Vec<(u32, u32)>) -> CharSet {
+        ranges.sort_unstable();
+
+        let mut canonical: Vec<(u32, u32)> = vec![];
+        for (start, end) in ranges {
+            match canonical.last_mut() {
+                Some((_, last_end)) if start <= last_end.saturating_add(1) => *last_end = (*last_end).max(end),
+                _ => canonical.push((start, end)),
+            }
+        }
+
+        CharSet { ranges: canonical }
+    }
+
+    /// The characters matched by the symbols of a set, each symbol is case folded before it is negated or combined
+    pub fn from_symbols(set: &[SetSymbol], case_insensitive: bool) -> CharSet {
+        let mut ranges = vec![];
+        for symbol in set {
+            ranges.extend(CharSet::from_symbol(symbol, case_insensitive).ranges);
+        }
+
+        CharSet::new(ranges)
+    }
+
+    fn from_symbol(symbol: &SetSymbol, case_insensitive: bool) -> CharSet {
+        let (set, negated) = match symbol {
+            SetSymbol::Char(c) => (CharSet::new(vec![(*c as u32, *c as u32)]), false),
+            SetSymbol::Range(start, end) => (CharSet::new(vec![(*start, *end)]), false),
+            SetSymbol::Class(class) => (CharSet::new(class.ranges().to_vec()), false),
+            SetSymbol::NotClass(class) => (CharSet::new(class.ranges().to_vec()), true),
+            SetSymbol::Property(property) => (CharSet::new(property.ranges().to_vec()), false),
+            SetSymbol::NotProperty(property) => (CharSet::new(property.ranges().to_vec()), true),
+            SetSymbol::Set(set) => return CharSet::from_symbols(set, case_insensitive),
+            SetSymbol::NotSet(set) => return CharSet::from_symbols(set, case_insensitive).complement(),
+            SetSymbol::Operation(operation, lhs, rhs) => {
+                let lhs = CharSet::from_symbols(lhs, case_insensitive);
+                let rhs = CharSet::from_symbols(rhs, case_insensitive);
+
+                return match operation {
+                    SetOperation::Intersection => lhs.intersection(&rhs),
+                    SetOperation::Difference => lhs.difference(&rhs),
+                    SetOperation::SymmetricDifference => lhs.symmetric_difference(&rhs),
+                }
+            },
+        };
+
+        let set = if case_insensitive { set.case_fold() } else { set };
+        if negated {
+            set.complement()
+        } else {
+            set
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        let c = c as u32;
+        let i = self.ranges.partition_point(|(_, end)| *end < c);
+        self.ranges.get(i).is_some_and(|(start, _)| *start <= c)
+    }
+
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        CharSet::new(self.ranges.iter().chain(other.ranges.iter()).copied().collect())
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        let mut ranges = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (start, end) = self.ranges[i];
+            let (other_start, other_end) = other.ranges[j];
+
+            if start.max(other_start) <= end.min(other_end) {
+                ranges.push((start.max(other_start), end.min(other_end)));
+            }
+
+            if end < other_end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        CharSet { ranges }
+    }
+
+    pub fn difference(&self, other: &CharSet) -> CharSet {
+        self.intersection(&other.complement())
+    }
+
+    pub fn symmetric_difference(&self, other: &CharSet) -> CharSet {
+        self.union(other).difference(&self.intersection(other))
+    }
+
+    /// Every character that is not in the set
+    pub fn complement(&self) -> CharSet {
+        let mut ranges = vec![];
+        let mut next = 0u32;
+
+        for (start, end) in &self.ranges {
+            if next < *start {
+                ranges.push((next, start - 1));
+            }
+            next = end + 1;
+        }
+        if next <= char::MAX as u32 {
+            ranges.push((next, char::MAX as u32));
+        }
+
+        CharSet { ranges }
+    }
+
+    /// Adds every character that is equivalent to a character in the set under simple case folding
+    pub fn case_fold(&self) -> CharSet {
+        let mut ranges = self.ranges.clone();
+        for (start, end) in &self.ranges {
+            ranges.extend(range_case_variants(*start, *end).into_iter().map(|c| (c as u32, c as u32)));
+        }
+
+        CharSet::new(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CharSet;
+
+    #[test]
+    fn test() {
+        let set = CharSet::new(vec![(10, 20), (0, 2), (15, 25), (3, 4), (30, 30)]);
+        assert_eq!(set.ranges(), &[(0, 4), (10, 25), (30, 30)]);
+        assert!(set.contains('\x00') && set.contains('\x19') && set.contains('\x1e'));
+        assert!(!set.contains('\x05') && !set.contains('\x1f'));
+
+        let other = CharSet::new(vec![(3, 12), (30, 40)]);
+        assert_eq!(set.union(&other).ranges(), &[(0, 25), (30, 40)]);
+        assert_eq!(set.intersection(&other).ranges(), &[(3, 4), (10, 12), (30, 30)]);
+        assert_eq!(set.difference(&other).ranges(), &[(0, 2), (13, 25)]);
+        assert_eq!(set.symmetric_difference(&other).ranges(), &[(0, 2), (5, 9), (13, 25), (31, 40)]);
+        assert_eq!(set.complement().ranges(), &[(5, 9), (26, 29), (31, char::MAX as u32)]);
+        assert_eq!(set.complement().complement(), set);
+        assert_eq!(CharSet::default().complement().ranges(), &[(0, char::MAX as u32)]);
+        assert_eq!(CharSet::default().complement().complement(), CharSet::default());
+
+        let set = CharSet::new(vec![('a' as u32, 'c' as u32)]).case_fold();
+        assert_eq!(set.ranges(), &[('A' as u32, 'C' as u32), ('a' as u32, 'c' as u32)]);
+    }
+}
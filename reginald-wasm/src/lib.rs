@@ -39,6 +39,19 @@ fn new_slices(slices: Vec<(usize, usize)>) -> Slices {
     Slices{slices}
 }
 
+/// The (start, size) in UTF-16 code units of every match, as javascript strings are indexed by UTF-16 code units rather than bytes or chars
+///
+/// The matches must be in order, the string is only walked over once.
+fn char_slices<'t>(string: &str, found: impl Iterator<Item = regex::Match<'t>>) -> Vec<(usize, usize)> {
+    let mut start = 0;
+    let mut last = 0;
+    found.map(|found| {
+        start += string[last..found.start()].encode_utf16().count();
+        last = found.start();
+        (start, found.as_str().encode_utf16().count())
+    }).collect()
+}
+
 
 #[wasm_bindgen]
 impl Regex {
@@ -54,11 +67,21 @@ impl Regex {
     }
 
     pub fn matches(&self, string: &str) -> Slices {
-        new_slices(self.internal.matches(string))
+        new_slices(char_slices(string, self.internal.find_iter(string)))
     }
 
     pub fn is_match(&self, string: &str) -> Option<Slice> {
-        self.internal.is_match(string).map(|slice| Slice::new(slice))
+        self.internal.is_match(string).map(|found| Slice::new(char_slices(string, std::iter::once(found))[0]))
+    }
+
+    /// Replaces the first match with the template, see `regex::Captures::expand`
+    pub fn replace(&self, string: &str, template: &str) -> String {
+        self.internal.replace(string, template).into_owned()
+    }
+
+    /// Replaces every match with the template, see `regex::Captures::expand`
+    pub fn replace_all(&self, string: &str, template: &str) -> String {
+        self.internal.replace_all(string, template).into_owned()
     }
 
     pub fn to_string(&self) -> String {
@@ -76,26 +76,7 @@ init()
             outputText.innerHTML = "";
             if (regex) {
                 let text = runText.innerText;
-                let replace_text = replaceInput.value;
-                let output = "";
-                let slices = regex.matches(text);
-                let len = slices.len();
-                let text_index = 0;
-                for (let i = 0; i < len; i++) {
-                    let slice = slices.get(i);
-                    if (text_index < slice.start) {
-                        output += text.slice(text_index, slice.start);
-                        text_index = slice.start;
-                    }
-                    output += replace_text;
-                    text_index += slice.size;
-                }
-
-                if (text_index < text.length) {
-                    output += text.slice(text_index, text.length);
-                }
-
-                outputText.innerText = output;
+                outputText.innerText = regex.replace_all(text, replaceInput.value);
             }
         });
 
@@ -1,4 +1,4 @@
-use std::{fs::File, process, fmt::Display, io::{Read, self}};
+use std::{fs::File, process, fmt::Display, io::{Read, self}, borrow::Cow};
 
 use clap::{Parser, ValueEnum};
 use reginald_lib::regex::{Regex};
@@ -46,44 +46,29 @@ fn main() {
         },
     };
 
+    if let Some(replace_str) = cli.replace_str {
+        let replaced = match cli.command {
+            Commands::MATCH => regex.replace(&input, replace_str.as_str()),
+            Commands::MATCHES => regex.replace_all(&input, replace_str.as_str()),
+            Commands::TEST => if regex.test(&input) {Cow::from(replace_str.as_str())} else {Cow::from(input.as_str())},
+        };
+        println!("{}", replaced);
+        return;
+    }
+
     let matches = match cli.command {
         Commands::MATCH => {
             match regex.is_match(&input) {
-                Some(sub_str) => vec![sub_str],
+                Some(found) => vec![found.range()],
                 None => vec![],
             }
         },
-        Commands::MATCHES => regex.matches(&input),
-        Commands::TEST => if regex.test(&input) {vec![(0,input.len())]} else {vec![]},
+        Commands::MATCHES => regex.matches(&input).iter().map(|found| found.range()).collect(),
+        Commands::TEST => if regex.test(&input) {vec![0..input.len()]} else {vec![]},
     };
-    
-    let input = input.chars().collect::<Vec<char>>();
-    match cli.replace_str {
-        Some(replace_str) => {
-            let mut matches = matches.iter().peekable();
-            let mut i = 0;
-            while i < input.len() {
-                if let Some((start, size)) = matches.peek() {
-                    if i < *start {
-                        print!("{}", input[i..*start].iter().collect::<String>());
-                        i = *start;
-                    } else if *start == i {
-                        print!("{}", replace_str);
-                        i += *size;
-                        matches.next();
-                    } else {
-                        unreachable!()
-                    }
-                } else {
-                    print!("{}", input[i..input.len()].iter().collect::<String>());
-                    break;
-                }
-            }
-            println!("");
-        },
-        None => for (start, size) in matches {
-            println!("{}", input[start..(start+size)].iter().collect::<String>())
-        },
+
+    for range in matches {
+        println!("{}", &input[range])
     }
 }
 